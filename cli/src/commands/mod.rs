@@ -1,35 +1,95 @@
-use std::net::UdpSocket;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use protocol::{Envelope, Message};
 mod output;
+mod request;
 
-pub fn req_rep(sock: std::net::UdpSocket, req: protocol::RequestFuncs) -> protocol::ResponseFuncs {
-    let msg = protocol::Message::Request(req);
-    let ser = protocol::serialize_message(msg);
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
 
-    sock.send(&ser).expect("Failed to write to server");
+// Sends the request and returns the reply to it. A server that doesn't speak our version tells
+// us the versions it does, the request is then repeated in the highest one we share.
+fn exchange<S: Read + Write>(stream: &mut S, token: &str, req: &protocol::RequestFuncs) -> Result<Message, String> {
+    let mut version = protocol::PROTOCOL_VERSION;
+    loop {
+        let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        let message = Message::Request(token.to_string(), req.clone());
+        protocol::write_frame(stream, &Envelope::Message { version, request_id, message })
+            .map_err(|e| e.to_string())?;
+        match protocol::read_frame(stream).map_err(|e| e.to_string())? {
+            Envelope::Message { request_id: id, message, .. } if id == request_id => return Ok(message),
+            // the server couldn't read the request id
+            Envelope::Message { message: message @ Message::Error(..), .. } => return Ok(message),
+            Envelope::Message { .. } => return Err("Response to another request".to_string()),
+            Envelope::Unsupported { min_version, max_version, .. } => match protocol::negotiate(min_version, max_version) {
+                Some(negotiated) if negotiated != version && negotiated >= req.min_version() => version = negotiated,
+                Some(negotiated) if negotiated != version => {
+                    return Err(format!("The server speaks protocol version {}, too old for this request", negotiated));
+                }
+                _ => {
+                    return Err(format!(
+                        "rbcli speaks protocol versions {}-{}, the server {}-{}",
+                        protocol::MIN_PROTOCOL_VERSION, protocol::PROTOCOL_VERSION, min_version, max_version
+                    ));
+                }
+            },
+        }
+    }
+}
 
-    let mut buf = [0u8; 1500];
-    let (len, _src) = sock
-        .recv_from(&mut buf)
-        .expect("Could not read into buffer");
+fn into_response(message: Message) -> protocol::ResponseFuncs {
+    match message {
+        Message::Response(resp) => resp,
+        Message::Error(code, e) => protocol::ResponseFuncs::Error(format!("{:?}: {}", code, e)),
+        Message::Request(..) => protocol::ResponseFuncs::Error("No valid response".to_string()),
+    }
+}
 
-    let buf = &mut buf[..len]; // resize buffer
+// node is host:port for TCP or the path of the server's unix socket
+pub fn req_rep(node_addr: &str, token: String, req: protocol::RequestFuncs) -> protocol::ResponseFuncs {
+    let resp = if node_addr.contains('/') {
+        UnixStream::connect(node_addr).map_err(|e| e.to_string())
+            .and_then(|mut stream| exchange(&mut stream, &token, &req))
+    } else {
+        TcpStream::connect(node_addr).map_err(|e| e.to_string())
+            .and_then(|mut stream| exchange(&mut stream, &token, &req))
+    };
 
-    let resp = protocol::deserialize_message(buf.to_vec());
-    if let protocol::Message::Response(resp) = resp {
-        return resp;
+    match resp {
+        Ok(message) => into_response(message),
+        Err(e) => protocol::ResponseFuncs::Error(format!("Could not talk to server at {}: {}", node_addr, e)),
     }
+}
 
-    return protocol::ResponseFuncs::Error("No valid response".to_string());
+fn follow<S: Read + Write>(stream: &mut S, token: String) -> Result<(), String> {
+    let mut message = exchange(stream, &token, &protocol::RequestFuncs::SubscribeEvents)?;
+    loop {
+        match into_response(message) {
+            protocol::ResponseFuncs::Event(event) => output::event(event),
+            protocol::ResponseFuncs::Error(e) => return Err(e),
+            _ => return Err("No valid response".to_string()),
+        }
+        message = match protocol::read_frame(stream) {
+            Ok(Envelope::Message { message, .. }) => message,
+            Ok(_) => return Err("No valid response".to_string()),
+            // the server went away
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+    }
 }
 
-fn handle(value: &str, sock: std::net::UdpSocket) -> protocol::ResponseFuncs {
-    if let Ok(protocol) = value.parse() {
-        req_rep(
-            sock.try_clone().expect("Could not clone socket"),
-            protocol
-        )
+// Prints events pushed by the node until it shuts down or the user interrupts
+pub fn follow_events(node_addr: &str, token: String) -> Result<(), String> {
+    if node_addr.contains('/') {
+        let mut stream = UnixStream::connect(node_addr)
+            .map_err(|e| format!("Could not talk to server at {}: {}", node_addr, e))?;
+        follow(&mut stream, token)
     } else {
-        protocol::ResponseFuncs::Error("Invalid Internal Value".to_string())
+        let mut stream = TcpStream::connect(node_addr)
+            .map_err(|e| format!("Could not talk to server at {}: {}", node_addr, e))?;
+        follow(&mut stream, token)
     }
 }
 
@@ -43,33 +103,51 @@ pub fn react(command: &str, sub_command: &str, matches: &clap::ArgMatches, sub_m
         false => output::human
     };
 
-    let socket = 
-        UdpSocket::bind("lightning:5000")
-        .expect("Could not bind client socket");
-
-    socket
-        .connect(node_addr)
-        .expect("Could not connect to server");
+    // tokens are generated by the server in lndata/tokens, requests without one are refused
+    let token = match matches.value_of("token").map(std::fs::read_to_string) {
+        Some(Ok(token)) => token.trim().to_string(),
+        Some(Err(e)) => {
+            fn_output_format(protocol::ResponseFuncs::Error(format!("Could not read token file: {}", e)));
+            return;
+        }
+        None => String::new(),
+    };
 
-    // println!("matches:{:#?}", &matches);
-    // println!("sub_matches:{:#?}", &sub_matches);
-    // println!("command:{}", &command);
-    // println!("sub_command:{}", &sub_command);
+    if sub_command == "forceclose" && !sub_matches.is_present("yes") {
+        fn_output_format(protocol::ResponseFuncs::Error(
+            "Force closing broadcasts our latest commitment transaction and locks our funds until its timelock expires\nRerun with --yes to confirm".to_string()
+        ));
+        return;
+    }
 
-    let resp = match sub_matches.values_of(sub_command) {
-        Some(values) => {
-            let value: Vec<String> = values
-                .into_iter()
-                .map(|v| {
-                    v.to_string()
-                })
-                .collect();
-            let command_and_value = format!("{},{},{}", command, sub_command, value.join(","));
-            handle(&command_and_value, socket)
+    if command == "events" {
+        if let Err(e) = follow_events(node_addr, token) {
+            fn_output_format(protocol::ResponseFuncs::Error(e));
         }
-        _ => {
-            protocol::ResponseFuncs::Error("Invalid Command or Arguments Provided\nTry running with --help or -h".to_string())
+        return;
+    }
+
+    // describegraph style export, assembled from the node and channel listings
+    if command == "graph" && sub_command == "describe" {
+        let nodes = req_rep(node_addr, token.clone(), protocol::RequestFuncs::GraphNodes);
+        let channels = req_rep(node_addr, token, protocol::RequestFuncs::GraphChannels);
+        match (nodes, channels) {
+            (protocol::ResponseFuncs::GraphNodes(nodes), protocol::ResponseFuncs::GraphChannels(channels)) => {
+                output::describe_graph(nodes, channels);
+            }
+            (protocol::ResponseFuncs::Error(e), _) | (_, protocol::ResponseFuncs::Error(e)) => {
+                fn_output_format(protocol::ResponseFuncs::Error(e));
+            }
+            _ => {
+                fn_output_format(protocol::ResponseFuncs::Error("No valid response".to_string()));
+            }
         }
+        return;
+    }
+
+    let resp = match request::build(command, sub_command, sub_matches) {
+        Ok(req) => req_rep(node_addr, token, req),
+        Err(e) => protocol::ResponseFuncs::Error(e),
     };
 
     fn_output_format(resp);
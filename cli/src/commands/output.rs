@@ -5,22 +5,49 @@ pub fn json(resp: protocol::ResponseFuncs) {
             json!({ "imported_addresses": addrs })
         }
         protocol::ResponseFuncs::GetNodeInfo(info) => {
-            json!({ "node_id": info })
+            json!(info)
         }
-        protocol::ResponseFuncs::PeerConnect | protocol::ResponseFuncs::ChannelCloseAll => {
+        protocol::ResponseFuncs::PeerConnect(peer) => {
+            json!({ 
+                "response": "Peer connected",
+                "peer": peer,
+            })
+        }
+        protocol::ResponseFuncs::ChannelCloseAll => {
             json!({ "response": "Request Acknowledged ..."})
         }
         protocol::ResponseFuncs::PeerList(peers) => {
             json!({ "peers": peers })
         }
-        protocol::ResponseFuncs::InvoiceCreate(invoice) => {
+        protocol::ResponseFuncs::PeerDisconnect(peer) => {
+            json!({ 
+                "response": "Peer disconnected",
+                "peer": peer,
+            })
+        }
+        protocol::ResponseFuncs::PeerBan(target) => {
+            json!({ 
+                "response": "Banned",
+                "banned": target,
+            })
+        }
+        protocol::ResponseFuncs::PeerUnban(target) => {
             json!({ 
-                "response": "Invoice Created ...",
-                "invoice": invoice
+                "response": "Unbanned",
+                "unbanned": target,
             })
         }
+        protocol::ResponseFuncs::PeerBanList(banned) => {
+            json!({ "banned": banned })
+        }
+        protocol::ResponseFuncs::InvoiceCreate(invoice) => {
+            json!(invoice)
+        }
         protocol::ResponseFuncs::ChannelCreate(c) => {
-            json!({ "channel": c })
+            json!({ 
+                "response": "Channel opening",
+                "channel": c,
+            })
         }
         protocol::ResponseFuncs::ChannelClose(c) => {
             json!({ 
@@ -28,16 +55,53 @@ pub fn json(resp: protocol::ResponseFuncs) {
                 "channel": c,
             })
         }
-        protocol::ResponseFuncs::ChannelList(l) => {
-            let channels: Vec<serde_json::Value> = l.into_iter().map(|c|{
-                serde_json::from_str(&c).unwrap()
-            }).collect();
+        protocol::ResponseFuncs::ChannelForceClose(c, txid) => {
+            json!({ 
+                "response": "Channel force closed",
+                "channel": c,
+                "commitment_txid": txid,
+            })
+        }
+        protocol::ResponseFuncs::ChannelList(channels) => {
             json!({ 
                 "channels": channels 
             })
         }
-        protocol::ResponseFuncs::InvoicePay => {
-            json!({ "response": "Invoice Paid" })
+        protocol::ResponseFuncs::ChannelListClosed(channels) => {
+            json!({ 
+                "closed_channels": channels 
+            })
+        }
+        protocol::ResponseFuncs::ChannelLabel(c, label) => {
+            json!({ 
+                "response": "Channel labeled",
+                "channel": c,
+                "label": label,
+            })
+        }
+        protocol::ResponseFuncs::InvoicePay(payment) => {
+            json!({ 
+                "response": "Payment sent",
+                "payment": payment,
+            })
+        }
+        protocol::ResponseFuncs::QueryRoute(route) => {
+            json!({ "route": route })
+        }
+        protocol::ResponseFuncs::GraphNodes(nodes) => {
+            json!({ "nodes": nodes })
+        }
+        protocol::ResponseFuncs::GraphChannels(channels) => {
+            json!({ "channels": channels })
+        }
+        protocol::ResponseFuncs::GraphNode(node) => {
+            json!({ "node": node })
+        }
+        protocol::ResponseFuncs::GraphChannel(channel) => {
+            json!({ "channel": channel })
+        }
+        protocol::ResponseFuncs::Event(event) => {
+            json!({ "event": event })
         }
         protocol::ResponseFuncs::Error(e) => {
             json!({ 
@@ -49,6 +113,68 @@ pub fn json(resp: protocol::ResponseFuncs) {
     println!("{}", serde_json::to_string_pretty(&res).unwrap());
 }
 
+pub fn describe_graph(nodes: Vec<protocol::GraphNodeInfo>, channels: Vec<protocol::GraphChannelInfo>) {
+    use serde_json::json;
+    let edges: Vec<serde_json::Value> = channels.into_iter().map(|c| {
+        json!({
+            "channel_id": c.short_channel_id,
+            "short_channel_id": protocol::short_channel_id_to_string(c.short_channel_id),
+            "node1_pub": c.node1,
+            "node2_pub": c.node2,
+            "node1_policy": c.node1_policy,
+            "node2_policy": c.node2_policy,
+        })
+    }).collect();
+    let res = json!({
+        "nodes": nodes,
+        "edges": edges,
+    });
+    println!("{}", serde_json::to_string_pretty(&res).unwrap());
+}
+
+// one compact JSON document per line, for piping into other tools
+pub fn event(event: protocol::NodeEvent) {
+    println!("{}", serde_json::to_string(&event).unwrap());
+}
+
+fn print_policy(node: &str, policy: &protocol::RoutingPolicy) {
+    println!("    {}: base fee {} msat, fee rate {} ppm, cltv delta {}, min htlc {} msat{}",
+        node, policy.fee_base_msat, policy.fee_proportional_millionths, policy.cltv_expiry_delta,
+        policy.htlc_minimum_msat, if policy.disabled { ", disabled" } else { "" });
+}
+
+fn print_graph_node(node: &protocol::GraphNodeInfo) {
+    println!("{} [{}]", node.node_id, node.alias);
+    println!("    color: {}", node.color);
+    for address in node.addresses.iter() {
+        println!("    address: {}", address);
+    }
+    println!("    last update: {}", node.last_update);
+}
+
+fn print_graph_channel(channel: &protocol::GraphChannelInfo) {
+    println!("{}", protocol::short_channel_id_to_string(channel.short_channel_id));
+    print_policy(&channel.node1, &channel.node1_policy);
+    print_policy(&channel.node2, &channel.node2_policy);
+}
+
+fn print_channel(c: &protocol::ChannelInfo) {
+    println!("{} [{:?}{}]", c.id, c.state, if c.is_public { ", public" } else { "" });
+    if let Some(ref label) = c.label {
+        println!("    label: {}", label);
+    }
+    println!("    peer: {}", c.peer);
+    if let Some(short_id) = c.short_channel_id {
+        println!("    short id: {}", protocol::short_channel_id_to_string(short_id));
+    }
+    if let Some(ref funding_txo) = c.funding_txo {
+        println!("    funding: {}", funding_txo);
+    }
+    println!("    value: {} sat", c.value_sats);
+    println!("    outbound: {} msat, inbound: {} msat, unavailable: {} msat",
+        c.outbound_capacity_msat, c.inbound_capacity_msat, c.unavailable_msat);
+}
+
 pub fn human(resp: protocol::ResponseFuncs) {
     match resp {
         protocol::ResponseFuncs::GetAddresses(addrs) => {
@@ -58,24 +184,153 @@ pub fn human(resp: protocol::ResponseFuncs) {
             }
         }
         protocol::ResponseFuncs::GetNodeInfo(info) => {
-            println!("{}", info);
+            println!("{} [{}]", info.node_id, info.alias);
+            println!("    version: {}", info.version);
+            println!("    network: {}, port: {}", info.network, info.port);
+            for address in info.listen_addresses.iter() {
+                println!("    listening on: {}", address);
+            }
+            for address in info.addresses.iter() {
+                println!("    announced address: {}", address);
+            }
+            println!("    block: {} {}{}", info.block_height, info.block_hash,
+                if info.synced_to_chain { "" } else { " (syncing)" });
+            println!("    channels: {} active, {} pending, {} inactive",
+                info.num_active_channels, info.num_pending_channels, info.num_inactive_channels);
+            println!("    peers: {}", info.num_peers);
+            println!("    spendable: {} msat, receivable: {} msat", info.spendable_msat, info.receivable_msat);
         }
-        protocol::ResponseFuncs::PeerConnect => {
-            println!("Request Acknowledged ...");
+        protocol::ResponseFuncs::PeerConnect(peer) => {
+            println!("Connected to {}", peer);
         }
         protocol::ResponseFuncs::PeerList(peers) => {
             println!("Connected Peers:");
             for peer in peers {
-                println!("{}", peer);
+                println!("{} [{}, connection {}]", peer.node_id, if peer.inbound { "inbound" } else { "outbound" }, peer.connection_id);
+                if let Some(address) = peer.address {
+                    println!("    address: {}", address);
+                }
+                println!("    connected since: {}", peer.connected_since);
+                println!("    sent: {} bytes, received: {} bytes", peer.bytes_sent, peer.bytes_received);
+                if let Some(features) = peer.features {
+                    println!("    announced features: {}", features);
+                }
+                for channel in peer.channels {
+                    println!("    channel: {}", channel);
+                }
+            }
+        }
+        protocol::ResponseFuncs::PeerDisconnect(peer) => {
+            println!("Disconnected {}", peer);
+        }
+        protocol::ResponseFuncs::PeerBan(target) => {
+            println!("Banned {}", target);
+        }
+        protocol::ResponseFuncs::PeerUnban(target) => {
+            println!("Unbanned {}", target);
+        }
+        protocol::ResponseFuncs::PeerBanList(banned) => {
+            println!("Banned Peers:");
+            for target in banned {
+                println!("{}", target);
             }
         }
         protocol::ResponseFuncs::InvoiceCreate(invoice) => {
             println!("Invoice created");
-            println!("{}", invoice);
+            println!("{}", invoice.invoice);
+            println!("    payment hash: {}", invoice.payment_hash);
+            if let Some(amount_msat) = invoice.amount_msat {
+                println!("    amount: {} msat", amount_msat);
+            }
+            println!("    description: {}", invoice.description);
+        }
+        protocol::ResponseFuncs::InvoicePay(payment) => {
+            println!("Payment sent to {}", payment.destination);
+            println!("    payment hash: {}", payment.payment_hash);
+            println!("    amount: {} msat, fee: {} msat, hops: {}",
+                payment.route.amount_msat, payment.route.total_fee_msat, payment.route.hops.len());
+        }
+        protocol::ResponseFuncs::ChannelCreate(c) => {
+            println!("Opening channel");
+            print_channel(&c);
+        }
+        protocol::ResponseFuncs::ChannelClose(c) => {
+            println!("Channel closed: {}", c);
+        }
+        protocol::ResponseFuncs::ChannelCloseAll => {
+            println!("Force closing all channels");
+        }
+        protocol::ResponseFuncs::ChannelForceClose(c, txid) => {
+            println!("Channel force closed: {}", c);
+            match txid {
+                Some(txid) => println!("Commitment transaction: {}", txid),
+                None => println!("No commitment transaction broadcast"),
+            }
+        }
+        protocol::ResponseFuncs::ChannelList(channels) => {
+            println!("Channels:");
+            for c in channels.iter() {
+                print_channel(c);
+            }
+        }
+        protocol::ResponseFuncs::ChannelListClosed(channels) => {
+            println!("Closed Channels:");
+            for c in channels {
+                let close_type = c.close_type.map_or("Unknown".to_string(), |t| format!("{:?}", t));
+                println!("{} [{}]", c.id, close_type);
+                if let Some(peer) = c.peer {
+                    println!("    peer: {}", peer);
+                }
+                if let Some(txid) = c.closing_txid {
+                    println!("    closing tx: {}", txid);
+                }
+                println!("    last seen open: {} msat outbound, {} msat inbound", c.last_outbound_capacity_msat, c.last_inbound_capacity_msat);
+                match (c.closed_at_height, c.spendable_at_height) {
+                    (Some(closed), Some(spendable)) => println!("    closed at: {}, spendable at: {}", closed, spendable),
+                    (Some(closed), None) => println!("    closed at: {}, funds not yet spendable", closed),
+                    _ => println!("    closing transaction not confirmed yet"),
+                }
+            }
+        }
+        protocol::ResponseFuncs::ChannelLabel(c, label) => {
+            println!("Channel {} labeled {}", c, label);
+        }
+        protocol::ResponseFuncs::QueryRoute(route) => {
+            println!("Route:");
+            // the last hop is the destination, it takes no fee
+            let last = route.hops.len().saturating_sub(1);
+            for (i, hop) in route.hops.iter().enumerate() {
+                println!("{} via {}", hop.node_id, protocol::short_channel_id_to_string(hop.short_channel_id));
+                if i < last {
+                    println!("    fee: {} msat, cltv delta: {}", hop.fee_msat, hop.cltv_expiry_delta);
+                }
+            }
+            println!("amount: {} msat, total fee: {} msat, total timelock: {} blocks",
+                route.amount_msat, route.total_fee_msat, route.total_cltv);
+        }
+        protocol::ResponseFuncs::GraphNodes(nodes) => {
+            println!("Nodes:");
+            for node in nodes.iter() {
+                print_graph_node(node);
+            }
+        }
+        protocol::ResponseFuncs::GraphChannels(channels) => {
+            println!("Channels:");
+            for channel in channels.iter() {
+                print_graph_channel(channel);
+            }
+        }
+        protocol::ResponseFuncs::GraphNode(node) => {
+            print_graph_node(&node);
+        }
+        protocol::ResponseFuncs::GraphChannel(channel) => {
+            print_graph_channel(&channel);
+        }
+        protocol::ResponseFuncs::Event(e) => {
+            event(e);
         }
         protocol::ResponseFuncs::Error(e) => {
             println!("{}", e);
         }
-        _ => {}
     };
 }
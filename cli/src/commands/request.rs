@@ -0,0 +1,101 @@
+use std::str::FromStr;
+use clap::ArgMatches;
+use protocol::{ChannelState, PaymentLimits, RequestFuncs};
+
+fn values<'a>(sub_matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+    sub_matches.values_of(name).map(|values| values.collect()).unwrap_or_default()
+}
+
+fn arg(values: &[&str], index: usize, what: &str) -> Result<String, String> {
+    values.get(index).map(|v| v.to_string()).ok_or(format!("Missing {}", what))
+}
+
+fn parse<T: FromStr>(value: &str, what: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid {}: {}", what, value))
+}
+
+fn parse_opt<T: FromStr>(value: Option<&str>, what: &str) -> Result<Option<T>, String> {
+    value.map(|v| parse(v, what)).transpose()
+}
+
+fn channel_state(mode: &str) -> Result<Option<ChannelState>, String> {
+    match mode {
+        "all" => Ok(None),
+        "live" | "active" => Ok(Some(ChannelState::Active)),
+        "pending" => Ok(Some(ChannelState::Pending)),
+        "inactive" => Ok(Some(ChannelState::Inactive)),
+        _ => Err(format!("Unknown channel list mode: {}, expected all, live, pending or inactive", mode)),
+    }
+}
+
+fn payment_limits(sub_matches: &ArgMatches) -> Result<PaymentLimits, String> {
+    let max_fee_percent: Option<f64> = parse_opt(sub_matches.value_of("max_fee_percent"), "max fee percent")?;
+    if let Some(percent) = max_fee_percent {
+        if percent.is_nan() || percent < 0.0 {
+            return Err(format!("Invalid max fee percent: {}", percent));
+        }
+    }
+    Ok(PaymentLimits {
+        max_fee_msat: parse_opt(sub_matches.value_of("max_fee_msat"), "max fee msat")?,
+        max_fee_percent,
+        max_total_cltv: parse_opt(sub_matches.value_of("max_total_cltv"), "max total cltv")?,
+    })
+}
+
+// The request for `rbcli <command> --<sub_command>`
+pub fn build(command: &str, sub_command: &str, sub_matches: &ArgMatches) -> Result<RequestFuncs, String> {
+    let args = values(sub_matches, sub_command);
+    let req = match (command, sub_command) {
+        ("info", "node") => RequestFuncs::GetNodeInfo,
+        ("info", "addresses") => RequestFuncs::GetAddresses,
+        ("invoice", "create") => RequestFuncs::InvoiceCreate {
+            amount_pico_btc: parse(&arg(&args, 0, "amount")?, "amount")?,
+            description: sub_matches.value_of("description").map(|d| d.to_string()),
+        },
+        ("invoice", "pay") => RequestFuncs::InvoicePay {
+            invoice: arg(&args, 0, "invoice")?,
+            amount_msat: parse_opt(args.get(1).cloned(), "amount")?,
+            limits: payment_limits(sub_matches)?,
+        },
+        ("invoice", "route") => RequestFuncs::QueryRoute {
+            target: arg(&args, 0, "target")?,
+            amount_msat: parse_opt(args.get(1).cloned(), "amount")?,
+            final_cltv: parse_opt(args.get(2).cloned(), "final cltv")?,
+        },
+        ("channel", "create") => RequestFuncs::ChannelCreate {
+            node_id: arg(&args, 0, "node id")?,
+            value_sat: parse(&arg(&args, 1, "channel value")?, "channel value")?,
+            push_msat: parse(&arg(&args, 2, "push value")?, "push value")?,
+            private: sub_matches.is_present("private"),
+            close_to: args.get(3).map(|v| v.to_string()),
+        },
+        ("channel", "kill") => RequestFuncs::ChannelClose {
+            channel: arg(&args, 0, "channel")?,
+            close_to: args.get(1).map(|v| v.to_string()),
+        },
+        ("channel", "forceclose") => RequestFuncs::ChannelForceClose { channel: arg(&args, 0, "channel")? },
+        ("channel", "killall") => RequestFuncs::ChannelCloseAll,
+        ("channel", "list") => RequestFuncs::ChannelList {
+            state: channel_state(&arg(&args, 0, "mode")?)?,
+            peer: args.get(1).map(|v| v.to_string()),
+        },
+        ("channel", "closed") => RequestFuncs::ChannelListClosed,
+        ("channel", "label") => RequestFuncs::ChannelLabel {
+            channel: arg(&args, 0, "channel")?,
+            label: arg(&args, 1, "label")?,
+        },
+        ("peer", "connect") => RequestFuncs::PeerConnect { node: arg(&args, 0, "peer")? },
+        ("peer", "list") => RequestFuncs::PeerList,
+        ("peer", "disconnect") => RequestFuncs::PeerDisconnect { node_id: arg(&args, 0, "node id")? },
+        ("peer", "ban") => RequestFuncs::PeerBan { target: arg(&args, 0, "node id or ip")? },
+        ("peer", "unban") => RequestFuncs::PeerUnban { target: arg(&args, 0, "node id or ip")? },
+        ("peer", "banned") => RequestFuncs::PeerBanList,
+        ("graph", "nodes") => RequestFuncs::GraphNodes,
+        ("graph", "channels") => RequestFuncs::GraphChannels,
+        ("graph", "node") => RequestFuncs::GraphNode { node: arg(&args, 0, "node")? },
+        ("graph", "channel") => RequestFuncs::GraphChannel { short_channel_id: arg(&args, 0, "short channel id")? },
+        ("events", _) => RequestFuncs::SubscribeEvents,
+        _ => return Err("Invalid Command or Arguments Provided\nTry running with --help or -h".to_string()),
+    };
+    Ok(req)
+}
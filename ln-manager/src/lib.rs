@@ -20,6 +20,7 @@ extern crate tokio_io;
 extern crate tokio_timer;
 extern crate tokio_net;
 extern crate futures_timer;
+extern crate protocol;
 
 #[macro_use]
 extern crate log;
@@ -34,6 +35,7 @@ pub mod utils;
 
 use std::collections::HashMap;
 use std::fs;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -43,19 +45,32 @@ use futures::channel::mpsc;
 use futures::{FutureExt, StreamExt};
 
 use bitcoin::network::constants;
+use lightning::chain::chaininterface::{ChainListener, ChainWatchInterface};
 use lightning::chain::keysinterface::{KeysInterface, KeysManager};
 use lightning::ln::channelmanager::{ChannelManager, PaymentHash, PaymentPreimage};
 use lightning::ln::peer_handler::PeerManager;
+use lightning::ln::msgs::NetAddress;
 use lightning::ln::{channelmanager, channelmonitor, peer_handler, router};
 use lightning::util::logger::{Level};
 use secp256k1::key::PublicKey;
 use secp256k1::{All, Secp256k1};
 
 use ln_bridge::connection::{Connection, SocketDescriptor};
-use ln_bridge::chain_monitor::{spawn_chain_monitor, ChainWatchInterfaceUtil, ChainBroadcaster, FeeEstimator};
+use ln_bridge::chain_monitor::{spawn_chain_monitor, ChainTip, ChainWatchInterfaceUtil, ChainBroadcaster, FeeEstimator};
 use ln_bridge::channel_monitor::ChannelMonitor;
+use ln_bridge::closed_channels::ClosedChannels;
+use ln_bridge::close_addresses::CloseAddresses;
+use ln_bridge::channel_labels::ChannelLabels;
+use ln_bridge::peer_store::{spawn_reconnect_supervisor, PeerStore};
+use ln_bridge::ban_list::BanList;
+use ln_bridge::connections::ConnectionRegistry;
+use ln_bridge::net_address::parse_onion;
+use ln_bridge::node_announcement::{spawn_node_announcer, NodeAnnouncer};
 use ln_bridge::channel_manager::RestoreArgs as RestoreManagerArgs;
+use ln_bridge::router::{spawn_router_persister, RestoreArgs as RestoreRouterArgs};
 use ln_bridge::event_handler::EventHandler;
+use ln_bridge::events::EventBus;
+use ln_bridge::webhooks::{spawn_webhook_dispatcher, Webhooks};
 use ln_bridge::rpc_client::RPCClient;
 use ln_bridge::log_printer::LogPrinter;
 use ln_bridge::settings::Settings;
@@ -69,7 +84,17 @@ pub struct LnManager<T: Larva> {
     pub router: Arc<router::Router>,
     pub event_notify: mpsc::Sender<()>,
     pub channel_manager: Arc<ChannelManager>,
+    pub monitor: Arc<ChannelMonitor>,
+    pub closed_channels: Arc<ClosedChannels<T>>,
+    pub close_addresses: Arc<CloseAddresses>,
+    pub channel_labels: Arc<ChannelLabels>,
+    pub chain_broadcaster: Arc<ChainBroadcaster<T>>,
+    pub chain_tip: Arc<ChainTip>,
     pub peer_manager: Arc<PeerManager<SocketDescriptor<T>>>,
+    pub peer_store: Arc<PeerStore>,
+    pub connections: Arc<ConnectionRegistry>,
+    pub events: Arc<EventBus>,
+    pub onion_address: Option<NetAddress>,
     pub payment_preimages: Arc<Mutex<HashMap<PaymentHash, PaymentPreimage>>>,
     pub secp_ctx: Secp256k1<All>,
     pub keys: Arc<KeysManager>,
@@ -154,6 +179,7 @@ impl<T: Larva> LnManager<T> {
                 fee_estimator.clone(),
             ),
             file_prefix: data_path.clone() + "/monitors",
+            funding_txos: Mutex::new(HashMap::new()),
         });
 
         let channel_manager = channelmanager::ChannelManager::try_restore(RestoreManagerArgs::new(
@@ -168,7 +194,23 @@ impl<T: Larva> LnManager<T> {
             keys.clone(),
         ));
 
-        let router = Arc::new(router::Router::new(
+        let events = Arc::new(EventBus::new());
+        if !settings.webhooks.urls.is_empty() {
+            let webhooks = Webhooks::new(&data_path, settings.webhooks.clone()).unwrap_or_else(|e| panic!("{}", e));
+            let _ = larva.clone().spawn_task(
+                spawn_webhook_dispatcher(Arc::new(webhooks), events.subscribe()).map(|_| Ok(()))
+            );
+        }
+
+        // registered after the monitors and the manager so closes are already processed
+        let closed_channels = Arc::new(ClosedChannels::new(&data_path, monitor.clone(), chain_broadcaster.clone(), events.clone()));
+        let closed_channels_listener: Arc<dyn ChainListener> = closed_channels.clone();
+        chain_watcher.register_listener(Arc::downgrade(&closed_channels_listener));
+        let close_addresses = Arc::new(CloseAddresses::new(&data_path, network, keys.get_shutdown_pubkey()));
+        let channel_labels = Arc::new(ChannelLabels::new(&data_path));
+
+        let router = router::Router::try_restore(RestoreRouterArgs::new(
+            data_path.clone(),
             PublicKey::from_secret_key(&secp_ctx, &keys.get_node_secret()),
             chain_watcher.clone(), // chain watch
             logger.clone(),
@@ -184,6 +226,20 @@ impl<T: Larva> LnManager<T> {
             logger.clone(),
         ));
 
+        let _ = larva.clone().spawn_task(
+            spawn_router_persister(router.clone(), data_path.clone()).map(|_| Ok(()))
+        );
+
+        let peer_store = Arc::new(PeerStore::new(&data_path));
+        let ban_list = Arc::new(BanList::new(&data_path));
+        let proxy = settings.lightning.proxy.as_ref().map(|proxy| {
+            proxy.parse::<SocketAddr>().expect("proxy must be an ip:port socket address")
+        });
+        let connections = Arc::new(ConnectionRegistry::new(ban_list.clone(), proxy, events.clone()));
+        let onion_address = settings.lightning.onion_address.as_ref().map(|onion| {
+            parse_onion(onion).unwrap_or_else(|e| panic!("{}", e))
+        });
+
         let payment_preimages = Arc::new(Mutex::new(HashMap::new()));
 
         // clone for move (handle receiver)
@@ -196,48 +252,87 @@ impl<T: Larva> LnManager<T> {
             channel_manager.clone(),
             chain_broadcaster.clone(), // chain broadcaster
             payment_preimages.clone(),
+            closed_channels.clone(),
+            close_addresses.clone(),
+            events.clone(),
             larva.clone(),
         );
 
-        let peer_manager_listener = peer_manager.clone();
-        let event_listener = event_notify.clone();
-
-        info!("Lightning Port binded on 0.0.0.0:{}", &settings.lightning.port);
-        let setup_larva = larva.clone();
-        let async_settings = settings.clone();
-
-        let _ = larva.clone().spawn_task(async move {
-            let addr = &format!("0.0.0.0:{}", async_settings.lightning.port);
-            let listener = tokio::net::tcp::TcpListener::bind(addr).await;
-            listener.unwrap()
-                .incoming()
-                .for_each(move |sock| {
-                    info!("Got new inbound connection, waiting on them to start handshake...");
-                    Connection::setup_inbound(
-                        peer_manager_listener.clone(),
-                        event_listener.clone(),
-                        sock.unwrap(),
-                        setup_larva.clone(),
-                    );
-                    future::ready(())
-                })
-                .map(|_| ()).await;
-            Ok(())
-        });
+        for addr in settings.lightning.listen_addresses() {
+            let peer_manager_listener = peer_manager.clone();
+            let connections_listener = connections.clone();
+            let event_listener = event_notify.clone();
+            let ban_list = ban_list.clone();
+            let setup_larva = larva.clone();
+
+            let _ = larva.clone().spawn_task(async move {
+                let listener = match tokio::net::tcp::TcpListener::bind(&addr).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        error!("Failed to bind Lightning Port on {}: {}", &addr, e);
+                        return Err(());
+                    }
+                };
+                info!("Lightning Port binded on {}", &addr);
+                listener
+                    .incoming()
+                    .for_each(move |sock| {
+                        let sock = sock.unwrap();
+                        if let Ok(addr) = sock.peer_addr() {
+                            if ban_list.is_banned_ip(&addr.ip()) {
+                                info!("Refusing inbound connection from banned {}", addr.ip());
+                                return future::ready(());
+                            }
+                        }
+                        info!("Got new inbound connection, waiting on them to start handshake...");
+                        Connection::setup_inbound(
+                            peer_manager_listener.clone(),
+                            connections_listener.clone(),
+                            event_listener.clone(),
+                            sock,
+                            setup_larva.clone(),
+                        );
+                        future::ready(())
+                    })
+                    .map(|_| ()).await;
+                Ok(())
+            });
+        }
 
+        let chain_tip = Arc::new(ChainTip::new());
         let _ = larva.clone().spawn_task(
             async {
                 spawn_chain_monitor(
                     fee_estimator,
                     rpc_client.clone(),
                     chain_watcher,
-                    chain_broadcaster,
+                    chain_broadcaster.clone(),
                     event_notify.clone(),
+                    events.clone(),
+                    chain_tip.clone(),
                     larva.clone(),
                 ).map(| _| Ok(()))
             }.await
         );
 
+        let _ = larva.clone().spawn_task(
+            spawn_reconnect_supervisor(
+                peer_store.clone(),
+                peer_manager.clone(),
+                connections.clone(),
+                channel_manager.clone(),
+                router.clone(),
+                event_notify.clone(),
+                larva.clone(),
+            ).map(|_| Ok(()))
+        );
+
+        let announcer = NodeAnnouncer::new(keys.get_node_secret(), &settings.node, onion_address.clone())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let _ = larva.clone().spawn_task(
+            spawn_node_announcer(announcer, router.clone()).map(|_| Ok(()))
+        );
+
         // TODO see below
         // let _ = larva.clone().spawn_task(Box::new(
         //     tokio::timer::Interval::new(Instant::now(), Duration::new(1, 0))
@@ -255,7 +350,17 @@ impl<T: Larva> LnManager<T> {
             router,
             event_notify,
             channel_manager,
+            monitor,
+            closed_channels,
+            close_addresses,
+            channel_labels,
+            chain_broadcaster,
+            chain_tip,
             peer_manager,
+            peer_store,
+            connections,
+            events,
+            onion_address,
             payment_preimages,
             secp_ctx,
             keys,
@@ -266,6 +371,12 @@ impl<T: Larva> LnManager<T> {
         Ok(ln_manager)
     }
 
+    /// Saves state that is otherwise only written periodically, call before exiting
+    pub fn shutdown(&self) {
+        if let Err(e) = ln_bridge::router::persist(&self.router, &self.settings.lightning.lndata) {
+            error!("Failed to persist network graph: {}", e);
+        }
+    }
 }
 
 fn get_seeds_from_time() -> (u64, u32) {
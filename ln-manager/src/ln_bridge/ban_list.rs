@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use secp256k1::key::PublicKey;
+
+use super::storage;
+use super::utils::{hex_str, hex_to_compressed_pubkey};
+
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    node_ids: HashSet<String>,
+    ips: HashSet<String>,
+}
+
+pub enum BanTarget {
+    Node(PublicKey),
+    Ip(IpAddr),
+}
+
+impl BanTarget {
+    pub fn parse(target: &str) -> Result<Self, String> {
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return Ok(BanTarget::Ip(ip));
+        }
+        if target.len() == 33 * 2 {
+            if let Some(node_id) = hex_to_compressed_pubkey(target) {
+                return Ok(BanTarget::Node(node_id));
+            }
+        }
+        Err(format!("Expected a node id or an IP address: {}", target))
+    }
+}
+
+/// Peers we refuse to talk to, persisted in lndata. IPs are checked before inbound handshakes,
+/// node ids once the handshake tells us who the peer is.
+pub struct BanList {
+    filename: String,
+    stored: Mutex<Stored>,
+}
+
+impl BanList {
+    pub fn new(data_path: &str) -> Self {
+        let filename = format!("{}/banned", data_path);
+        Self {
+            stored: Mutex::new(storage::read_json(&filename)),
+            filename,
+        }
+    }
+
+    pub fn is_banned_node(&self, node_id: &PublicKey) -> bool {
+        self.stored.lock().unwrap().node_ids.contains(&hex_str(&node_id.serialize()))
+    }
+
+    pub fn is_banned_ip(&self, ip: &IpAddr) -> bool {
+        self.stored.lock().unwrap().ips.contains(&ip.to_string())
+    }
+
+    pub fn ban(&self, target: &BanTarget) {
+        let mut stored = self.stored.lock().unwrap();
+        match target {
+            BanTarget::Node(node_id) => stored.node_ids.insert(hex_str(&node_id.serialize())),
+            BanTarget::Ip(ip) => stored.ips.insert(ip.to_string()),
+        };
+        self.persist(&stored);
+    }
+
+    /// false if the target wasn't banned
+    pub fn unban(&self, target: &BanTarget) -> bool {
+        let mut stored = self.stored.lock().unwrap();
+        let removed = match target {
+            BanTarget::Node(node_id) => stored.node_ids.remove(&hex_str(&node_id.serialize())),
+            BanTarget::Ip(ip) => stored.ips.remove(&ip.to_string()),
+        };
+        if removed {
+            self.persist(&stored);
+        }
+        removed
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        let stored = self.stored.lock().unwrap();
+        let mut banned: Vec<String> = stored.node_ids.iter().chain(stored.ips.iter()).cloned().collect();
+        banned.sort();
+        banned
+    }
+
+    fn persist(&self, stored: &Stored) {
+        if let Err(e) = storage::write_json(&self.filename, stored) {
+            error!("Failed to write ban list: {}", e);
+        }
+    }
+}
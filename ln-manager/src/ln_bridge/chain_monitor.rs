@@ -1,3 +1,4 @@
+use super::events::EventBus;
 use super::rpc_client::{GetHeaderResponse, RPCClient};
 use super::utils::hex_to_vec;
 
@@ -17,6 +18,7 @@ use futures::executor::block_on;
 use futures_timer::Interval;
 
 use lightning::chain::chaininterface;
+use protocol::NodeEvent;
 pub use lightning::chain::chaininterface::{ChainWatchInterface, ChainWatchInterfaceUtil};
 
 use bitcoin::blockdata::block::Block;
@@ -122,6 +124,47 @@ impl chaininterface::FeeEstimator for FeeEstimator {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct Tip {
+    // last block handed to rust-lightning, 0 and empty until the first poll
+    pub height: u32,
+    pub hash: String,
+    // headers bitcoind has, ahead of height while it or we are catching up
+    pub headers: u32,
+    pub initial_block_download: bool,
+}
+
+impl Tip {
+    pub fn is_synced(&self) -> bool {
+        !self.hash.is_empty() && !self.initial_block_download && self.height >= self.headers
+    }
+}
+
+// Where the chain monitor is, for getinfo
+pub struct ChainTip {
+    tip: Mutex<Tip>,
+}
+
+impl ChainTip {
+    pub fn new() -> Self {
+        Self { tip: Mutex::new(Tip::default()) }
+    }
+
+    pub fn get(&self) -> Tip {
+        self.tip.lock().unwrap().clone()
+    }
+
+    fn update(&self, v: &serde_json::Value, block: Option<(u32, String)>) {
+        let mut tip = self.tip.lock().unwrap();
+        tip.headers = v["headers"].as_u64().unwrap_or(0) as u32;
+        tip.initial_block_download = v["initialblockdownload"].as_bool().unwrap_or(false);
+        if let Some((height, hash)) = block {
+            tip.height = height;
+            tip.hash = hash;
+        }
+    }
+}
+
 pub struct ChainBroadcaster<T> {
     txn_to_broadcast: Mutex<HashMap<Sha256dHash, bitcoin::blockdata::transaction::Transaction>>,
     rpc_client: Arc<RPCClient>,
@@ -137,6 +180,17 @@ impl<T> ChainBroadcaster<T> {
         }
     }
 
+    pub fn was_broadcast(&self, txid: &Sha256dHash) -> bool {
+        self.txn_to_broadcast.lock().unwrap().contains_key(txid)
+    }
+
+    // Transactions we broadcast which spend any output of the given transaction
+    pub fn get_spending_txn(&self, txid: &Sha256dHash) -> Vec<bitcoin::blockdata::transaction::Transaction> {
+        self.txn_to_broadcast.lock().unwrap().values().filter(|tx| {
+            tx.input.iter().any(|input| input.previous_output.txid == *txid)
+        }).cloned().collect()
+    }
+
     async fn rebroadcast_txn(&self) {
         let txn = self.txn_to_broadcast.lock().unwrap();
 
@@ -356,6 +410,8 @@ pub async fn spawn_chain_monitor(
     chain_watcher: Arc<ChainWatchInterfaceUtil>,
     chain_broadcaster: Arc<ChainBroadcaster<impl Larva>>,
     event_notify: mpsc::Sender<()>,
+    events: Arc<EventBus>,
+    chain_tip: Arc<ChainTip>,
     larva: impl Larva,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     let _ = larva.clone().spawn_task(async { 
@@ -373,37 +429,44 @@ pub async fn spawn_chain_monitor(
             let chain_watcher = chain_watcher.clone();
             let chain_broadcaster = chain_broadcaster.clone();
             let mut event_notify = event_notify.clone();
+            let events = events.clone();
+            let chain_tip = chain_tip.clone();
             let larva = larva.clone();
             let _ = larva.spawn_task(async move {
                 let v = rpc_client.make_rpc_call("getblockchaininfo", &[], false).await?;
                 let new_block = v["bestblockhash"].as_str().unwrap().to_string();
+                let new_height = v["blocks"].as_u64().unwrap_or(0) as u32;
                 let old_block = cur_block.lock().unwrap().clone();
 
                 if new_block == old_block {
+                    chain_tip.update(&v, None);
                     return Ok(());
                 }
 
                 *cur_block.lock().unwrap() = new_block.clone();
                 if old_block == "" {
+                    chain_tip.update(&v, Some((new_height, new_block)));
                     return Ok(()); 
                 }
 
-                let (events_tx, events_rx): (mpsc::Sender<ForkStep>, mpsc::Receiver<ForkStep>) = mpsc::channel(1);
+                let (steps_tx, steps_rx): (mpsc::Sender<ForkStep>, mpsc::Receiver<ForkStep>) = mpsc::channel(1);
 
+                let new_block_hash = new_block.clone();
                 find_fork(
-                    events_tx,
+                    steps_tx,
                     new_block,
                     old_block,
                     rpc_client.clone(),
                 ).await;
 
-                let events: Vec<ForkStep> = events_rx.collect().await;
+                let fork_steps: Vec<ForkStep> = steps_rx.collect().await;
 
-                let actions = events.into_iter().rev().map(|event| {
+                let actions = fork_steps.into_iter().rev().map(|step| {
                     let client = rpc_client.clone();
                     let watcher = chain_watcher.clone();
+                    let events = events.clone();
                     async move {
-                        match event {
+                        match step {
                             ForkStep::DisconnectBlock(ref header, height) => {
                                 info!("Disconnecting block {}", header.bitcoin_hash().to_hex());
                                 watcher.block_disconnected(header, height);
@@ -417,12 +480,17 @@ pub async fn spawn_chain_monitor(
                                 ).unwrap();
                                 watcher.block_connected_with_filtering(&block, block_height);
                                 info!("Connecting block {}, Height: {}", block.bitcoin_hash().to_hex(), &block_height);
+                                events.publish(NodeEvent::NewBlock {
+                                    height: block_height,
+                                    hash: block.bitcoin_hash().to_hex(),
+                                });
                             }
                         }
                     }
                 });
                 
                 let _ = future::join_all(actions).await;
+                chain_tip.update(&v, Some((new_height, new_block_hash)));
                 let _ = FeeEstimator::update_values(fee_estimator, rpc_client).await;
                 let _ = event_notify.try_send(());
                 chain_broadcaster.rebroadcast_txn().await;
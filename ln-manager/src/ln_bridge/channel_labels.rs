@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::storage;
+use super::utils::hex_str;
+
+/// User assigned channel labels, persisted by channel id
+pub struct ChannelLabels {
+    filename: String,
+    labels: Mutex<HashMap<String, String>>,
+}
+
+impl ChannelLabels {
+    pub fn new(data_path: &str) -> Self {
+        let filename = format!("{}/channel_labels", data_path);
+        Self {
+            labels: Mutex::new(storage::read_json(&filename)),
+            filename,
+        }
+    }
+
+    pub fn get(&self, channel_id: &[u8; 32]) -> Option<String> {
+        self.labels.lock().unwrap().get(&hex_str(channel_id)).cloned()
+    }
+
+    pub fn find(&self, label: &str) -> Option<String> {
+        self.labels.lock().unwrap().iter()
+            .find(|(_, l)| l.as_str() == label)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Sets the label of a channel, labels are unique
+    pub fn set(&self, channel_id: &[u8; 32], label: String) -> Result<(), String> {
+        let id = hex_str(channel_id);
+        let mut labels = self.labels.lock().unwrap();
+        if labels.iter().any(|(other, l)| *l == label && *other != id) {
+            return Err(format!("Label {} is already used by another channel", label));
+        }
+        labels.insert(id, label);
+        if let Err(e) = storage::write_json(&self.filename, &*labels) {
+            error!("Failed to write channel labels: {}", e);
+        }
+        Ok(())
+    }
+}
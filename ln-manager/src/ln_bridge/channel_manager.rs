@@ -51,11 +51,17 @@ impl RestoreArgs {
     }
 }
 
+// the default config, channels opened by us may override announced_channel
+pub fn user_config() -> UserConfig {
+    let mut config = UserConfig::new();
+    config.channel_options.fee_proportional_millionths = FEE_PROPORTIONAL_MILLIONTHS;
+    config.channel_options.announced_channel = ANNOUNCE_CHANNELS;
+    config
+}
+
 impl Restorable<RestoreArgs, Arc<ChannelManager>> for ChannelManager {
     fn try_restore(args: RestoreArgs) -> Arc<ChannelManager> {
-        let mut config = UserConfig::new();
-        config.channel_options.fee_proportional_millionths = FEE_PROPORTIONAL_MILLIONTHS;
-        config.channel_options.announced_channel = ANNOUNCE_CHANNELS;
+        let config = user_config();
 
         if let Ok(mut f) = fs::File::open(args.data_path + "/manager_data") {
             let (_last_block_hash, manager) = {
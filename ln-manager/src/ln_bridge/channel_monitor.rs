@@ -1,5 +1,6 @@
 use std::fs;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::io::Cursor;
 
 use bitcoin_hashes::hex::{ToHex, FromHex};
@@ -15,9 +16,15 @@ use super::log_printer::LogPrinter;
 pub struct ChannelMonitor {
     pub monitor: Arc<channelmonitor::SimpleManyChannelMonitor<chain::transaction::OutPoint>>,
     pub file_prefix: String,
+    // channel_id -> funding outpoint, filled as monitors are added
+    pub funding_txos: Mutex<HashMap<[u8; 32], chain::transaction::OutPoint>>,
 }
 
 impl ChannelMonitor {
+    pub fn get_funding_txo(&self, channel_id: &[u8; 32]) -> Option<chain::transaction::OutPoint> {
+        self.funding_txos.lock().unwrap().get(channel_id).cloned()
+    }
+
     pub fn load_from_disk(
         file_prefix: &String,
     ) -> Vec<(chain::transaction::OutPoint, channelmonitor::ChannelMonitor)> {
@@ -133,6 +140,7 @@ impl channelmonitor::ManyChannelMonitor for ChannelMonitor {
         if need_bk {
             try_fs!(fs::remove_file(&bk_filename));
         }
+        self.funding_txos.lock().unwrap().insert(funding_txo.to_channel_id(), funding_txo);
         self.monitor.add_update_monitor(funding_txo, monitor)
     }
 
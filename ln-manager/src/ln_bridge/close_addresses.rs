@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::Address;
+use bitcoin::util::key;
+use lightning::ln::channelmanager::ChannelDetails;
+use secp256k1::key::PublicKey;
+
+use protocol::CloseType;
+
+use super::storage;
+use super::utils::hex_str;
+
+// Our output of a confirmed cooperative close, waiting to be moved to its close address
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingSweep {
+    pub channel_id: String,
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub address: String,
+    // height of the last failed attempt, retried once a later block arrives
+    #[serde(default)]
+    pub attempted_at: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    // set at channel creation, before we know the channel id
+    by_user_id: HashMap<u64, String>,
+    by_channel_id: HashMap<String, String>,
+    #[serde(default)]
+    pending_sweeps: Vec<PendingSweep>,
+}
+
+/// Addresses cooperative closes should end up paying to.
+///
+/// The closing transaction itself can't pay there: rust-lightning doesn't support
+/// upfront_shutdown_script or a per channel shutdown script yet, closing transactions always pay
+/// to the KeysManager shutdown key (imported into bitcoind as "rust-lightning cooperative
+/// close"). Once such a close confirms we sweep our output from there to the requested address,
+/// paying a second fee. Force closes and closes initiated by the peer pay to keys of our own
+/// wallet and are not swept, their address is dropped once the close confirms.
+pub struct CloseAddresses {
+    filename: String,
+    network: Network,
+    shutdown_script: Script,
+    stored: Mutex<Stored>,
+}
+
+impl CloseAddresses {
+    pub fn new(data_path: &str, network: Network, shutdown_pubkey: PublicKey) -> Self {
+        let filename = format!("{}/close_addresses", data_path);
+        let shutdown_script = Address::p2wpkh(&key::PublicKey { compressed: true, key: shutdown_pubkey }, network).script_pubkey();
+        Self {
+            stored: Mutex::new(storage::read_json(&filename)),
+            filename,
+            network,
+            shutdown_script,
+        }
+    }
+
+    pub fn validate(&self, address: &str) -> Result<(), String> {
+        match Address::from_str(address) {
+            Ok(addr) => {
+                if addr.network != self.network {
+                    Err(format!("Close address {} is not a {} address", address, self.network))
+                } else {
+                    Ok(())
+                }
+            }
+            Err(_) => Err(format!("Invalid close address: {}", address)),
+        }
+    }
+
+    pub fn set_for_user_id(&self, user_id: u64, address: String) {
+        let mut stored = self.stored.lock().unwrap();
+        stored.by_user_id.insert(user_id, address);
+        self.persist(&stored);
+    }
+
+    pub fn set_for_channel(&self, channel_id: &[u8; 32], address: String) {
+        let mut stored = self.stored.lock().unwrap();
+        stored.by_channel_id.insert(hex_str(channel_id), address);
+        self.persist(&stored);
+    }
+
+    /// Moves addresses given at creation over to the channel id once the channel shows up.
+    pub fn update(&self, channels: &[ChannelDetails]) {
+        let mut stored = self.stored.lock().unwrap();
+        if stored.by_user_id.is_empty() {
+            return;
+        }
+        let mut changed = false;
+        for channel in channels {
+            if let Some(address) = stored.by_user_id.remove(&channel.user_id) {
+                stored.by_channel_id.insert(hex_str(&channel.channel_id), address);
+                changed = true;
+            }
+        }
+        if changed {
+            self.persist(&stored);
+        }
+    }
+
+    /// Queues the sweep of our output in the confirmed closing transaction if the channel has a
+    /// close address. Kept in lndata until it succeeds.
+    pub fn queue_sweep(&self, channel_id: &[u8; 32], close_type: &CloseType, closing_tx: &Transaction) {
+        let mut stored = self.stored.lock().unwrap();
+        let address = match stored.by_channel_id.remove(&hex_str(channel_id)) {
+            Some(address) => address,
+            None => return,
+        };
+        if *close_type != CloseType::Cooperative {
+            info!("Channel {} was not closed cooperatively, not sweeping to {}", hex_str(channel_id), &address);
+            self.persist(&stored);
+            return;
+        }
+        match self.find_output(closing_tx) {
+            Some((vout, value)) => stored.pending_sweeps.push(PendingSweep {
+                channel_id: hex_str(channel_id),
+                txid: closing_tx.txid().to_string(),
+                vout,
+                value,
+                address,
+                attempted_at: None,
+            }),
+            None => info!("Nothing to sweep for channel {}, no output of ours in the closing tx", hex_str(channel_id)),
+        }
+        self.persist(&stored);
+    }
+
+    /// Drops the queued sweep of a closing transaction which was reorged out, the address is
+    /// used again once the close confirms anew.
+    pub fn unqueue_sweep(&self, channel_id: &str, closing_txid: &str) {
+        let mut stored = self.stored.lock().unwrap();
+        let position = stored.pending_sweeps.iter().position(|sweep| {
+            sweep.channel_id == channel_id && sweep.txid == closing_txid
+        });
+        if let Some(position) = position {
+            let sweep = stored.pending_sweeps.remove(position);
+            stored.by_channel_id.insert(sweep.channel_id, sweep.address);
+            self.persist(&stored);
+        }
+    }
+
+    /// Sweeps not yet attempted at this height
+    pub fn due_sweeps(&self, height: u32) -> Vec<PendingSweep> {
+        self.stored.lock().unwrap().pending_sweeps.iter()
+            .filter(|sweep| sweep.attempted_at.map_or(true, |attempted_at| attempted_at < height))
+            .cloned()
+            .collect()
+    }
+
+    pub fn sweep_done(&self, txid: &str, vout: u32) {
+        let mut stored = self.stored.lock().unwrap();
+        stored.pending_sweeps.retain(|sweep| !(sweep.txid == txid && sweep.vout == vout));
+        self.persist(&stored);
+    }
+
+    pub fn sweep_failed(&self, txid: &str, vout: u32, height: u32) {
+        let mut stored = self.stored.lock().unwrap();
+        for sweep in stored.pending_sweeps.iter_mut().filter(|sweep| sweep.txid == txid && sweep.vout == vout) {
+            sweep.attempted_at = Some(height);
+        }
+        self.persist(&stored);
+    }
+
+    /// Our output (vout, value) in a cooperative closing transaction
+    fn find_output(&self, tx: &Transaction) -> Option<(u32, u64)> {
+        tx.output.iter().enumerate().find(|(_, output)| {
+            output.script_pubkey == self.shutdown_script
+        }).map(|(vout, output)| (vout as u32, output.value))
+    }
+
+    fn persist(&self, stored: &Stored) {
+        if let Err(e) = storage::write_json(&self.filename, stored) {
+            error!("Failed to write close addresses: {}", e);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin_hashes::hex::ToHex;
+use bitcoin_hashes::sha256d::Hash as Sha256dHash;
+
+use lightning::chain::chaininterface::ChainListener;
+use lightning::ln::channelmanager::ChannelManager;
+use secp256k1::key::PublicKey;
+
+use protocol::{CloseType, ClosedChannelInfo, NodeEvent};
+
+use super::chain_monitor::ChainBroadcaster;
+use super::channel_monitor::ChannelMonitor;
+use super::events::EventBus;
+use super::storage;
+use super::utils::hex_str;
+use crate::executor::Larva;
+
+// BOLT 3 commitment transactions carry the obscured commitment number in the locktime
+// (upper byte 0x20) and the sequence (upper byte 0x80), cooperative closes don't
+fn is_commitment_tx(tx: &Transaction) -> bool {
+    tx.input.len() == 1 && (tx.lock_time >> 8*3) == 0x20 && (tx.input[0].sequence >> 8*3) == 0x80
+}
+
+// Revocation path witnesses are <sig> 1 <script> for to_local and
+// <sig> <revocationpubkey> <script> for htlc outputs
+fn is_justice_tx(tx: &Transaction, commitment_txid: &Sha256dHash) -> bool {
+    tx.input.iter().any(|input| {
+        input.previous_output.txid == *commitment_txid
+            && input.witness.len() == 3
+            && (input.witness[1] == vec![1] || input.witness[1].len() == 33)
+    })
+}
+
+struct OpenChannel {
+    short_channel_id: Option<u64>,
+    peer: PublicKey,
+    value_sats: u64,
+    outbound_capacity_msat: u64,
+    inbound_capacity_msat: u64,
+}
+
+struct State {
+    // last seen list_channels(), to notice channels confirming or going away
+    open: HashMap<[u8; 32], OpenChannel>,
+    // the first list after startup is only remembered, its channels opened earlier
+    seen_channels: bool,
+    // closes we initiated ourselves and haven't seen disappear yet
+    intents: HashMap<[u8; 32], CloseType>,
+    closed: Vec<ClosedChannelInfo>,
+    // confirmed closes not yet handed to CloseAddresses
+    confirmed_closes: Vec<([u8; 32], CloseType, Transaction)>,
+    // channels whose confirmed close was reorged out since the last take
+    reorged_closes: Vec<(String, String)>,
+}
+
+/// Keeps a persisted history of closed channels. Channels are recorded when they drop out of
+/// ChannelManager::list_channels() and completed once the transaction spending the funding
+/// output confirms (registered as a ChainListener after the channel monitors). Publishes
+/// ChannelOpened and ChannelClosed as it notices them.
+pub struct ClosedChannels<T: Larva> {
+    filename: String,
+    monitor: Arc<ChannelMonitor>,
+    broadcaster: Arc<ChainBroadcaster<T>>,
+    events: Arc<EventBus>,
+    best_height: AtomicUsize,
+    state: Mutex<State>,
+}
+
+impl<T: Larva> ClosedChannels<T> {
+    pub fn new(data_path: &str, monitor: Arc<ChannelMonitor>, broadcaster: Arc<ChainBroadcaster<T>>, events: Arc<EventBus>) -> Self {
+        let filename = format!("{}/closed_channels", data_path);
+        let closed: Vec<ClosedChannelInfo> = storage::read_json(&filename);
+        Self {
+            filename,
+            monitor,
+            broadcaster,
+            events,
+            best_height: AtomicUsize::new(0),
+            state: Mutex::new(State {
+                open: HashMap::new(),
+                seen_channels: false,
+                intents: HashMap::new(),
+                closed,
+                confirmed_closes: Vec::new(),
+                reorged_closes: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn list(&self) -> Vec<ClosedChannelInfo> {
+        self.state.lock().unwrap().closed.clone()
+    }
+
+    pub fn note_local_close(&self, channel_id: [u8; 32], close_type: CloseType) {
+        self.state.lock().unwrap().intents.insert(channel_id, close_type);
+    }
+
+    /// Diffs the current channel list against the last one, channels which went away are
+    /// moved to the history and newly confirmed ones announced.
+    pub fn update(&self, channel_manager: &Arc<ChannelManager>) {
+        let open: HashMap<[u8; 32], OpenChannel> = channel_manager
+            .list_channels()
+            .into_iter()
+            .map(|channel| {
+                (channel.channel_id, OpenChannel {
+                    short_channel_id: channel.short_channel_id,
+                    peer: channel.remote_network_id,
+                    value_sats: channel.channel_value_satoshis,
+                    outbound_capacity_msat: channel.outbound_capacity_msat,
+                    inbound_capacity_msat: channel.inbound_capacity_msat,
+                })
+            })
+            .collect();
+
+        let mut lock = self.state.lock().unwrap();
+        let state = &mut *lock;
+        let mut changed = false;
+        if state.seen_channels {
+            for (channel_id, channel) in open.iter() {
+                let was_confirmed = state.open.get(channel_id).map_or(false, |c| c.short_channel_id.is_some());
+                if let (Some(short_channel_id), false) = (channel.short_channel_id, was_confirmed) {
+                    self.events.publish(NodeEvent::ChannelOpened {
+                        channel_id: hex_str(channel_id),
+                        peer: hex_str(&channel.peer.serialize()),
+                        short_channel_id,
+                        value_sats: channel.value_sats,
+                    });
+                }
+            }
+        }
+        state.seen_channels = true;
+        for (channel_id, channel) in state.open.drain() {
+            if open.contains_key(&channel_id) {
+                continue;
+            }
+            let id = hex_str(&channel_id);
+            if !state.closed.iter().any(|record| record.id == id) {
+                let intent = state.intents.remove(&channel_id);
+                state.closed.push(self.new_record(&channel_id, Some(channel), intent));
+                changed = true;
+            }
+        }
+        state.open = open;
+        if changed {
+            self.persist(&state.closed);
+        }
+    }
+
+    pub fn take_confirmed_closes(&self) -> Vec<([u8; 32], CloseType, Transaction)> {
+        self.state.lock().unwrap().confirmed_closes.drain(..).collect()
+    }
+
+    /// Channel ids and closing txids of closes which left the chain again
+    pub fn take_reorged_closes(&self) -> Vec<(String, String)> {
+        self.state.lock().unwrap().reorged_closes.drain(..).collect()
+    }
+
+    pub fn best_height(&self) -> u32 {
+        self.best_height.load(Ordering::Acquire) as u32
+    }
+
+    /// Called with the txids of SpendableOutputs, a local commitment becomes ours once its
+    /// delayed output matures.
+    pub fn spendable_outputs(&self, txids: &[Sha256dHash]) {
+        let height = self.best_height.load(Ordering::Acquire) as u32;
+        let txids: Vec<String> = txids.iter().map(|txid| txid.to_hex()).collect();
+        let mut state = self.state.lock().unwrap();
+        let mut changed = false;
+        for record in state.closed.iter_mut() {
+            if record.spendable_at_height.is_some() {
+                continue;
+            }
+            if let Some(ref closing_txid) = record.closing_txid {
+                if txids.contains(closing_txid) {
+                    record.spendable_at_height = Some(height);
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.persist(&state.closed);
+        }
+    }
+
+    fn new_record(&self, channel_id: &[u8; 32], channel: Option<OpenChannel>, intent: Option<CloseType>) -> ClosedChannelInfo {
+        let funding_txo = self.monitor.get_funding_txo(channel_id).map(|txo| {
+            format!("{}:{}", txo.txid.to_hex(), txo.index)
+        });
+        ClosedChannelInfo {
+            id: hex_str(channel_id),
+            short_channel_id: channel.as_ref().and_then(|c| c.short_channel_id),
+            peer: channel.as_ref().map(|c| hex_str(&c.peer.serialize())),
+            funding_txo,
+            close_type: intent,
+            closing_txid: None,
+            last_outbound_capacity_msat: channel.as_ref().map_or(0, |c| c.outbound_capacity_msat),
+            last_inbound_capacity_msat: channel.as_ref().map_or(0, |c| c.inbound_capacity_msat),
+            closed_at_height: None,
+            spendable_at_height: None,
+        }
+    }
+
+    fn classify(&self, tx: &Transaction) -> CloseType {
+        let txid = tx.txid();
+        if !is_commitment_tx(tx) {
+            CloseType::Cooperative
+        } else if self.broadcaster.was_broadcast(&txid) {
+            CloseType::LocalForce
+        } else if self.broadcaster.get_spending_txn(&txid).iter().any(|spend| is_justice_tx(spend, &txid)) {
+            // the monitors see the block before us and already broadcast the penalty
+            CloseType::Breach
+        } else {
+            CloseType::RemoteForce
+        }
+    }
+
+    fn persist(&self, closed: &Vec<ClosedChannelInfo>) {
+        if let Err(e) = storage::write_json(&self.filename, closed) {
+            error!("Failed to write closed channel history: {}", e);
+        }
+    }
+}
+
+impl<T: Larva> ChainListener for ClosedChannels<T> {
+    fn block_connected(&self, _header: &BlockHeader, height: u32, txn_matched: &[&Transaction], _indexes_of_txn_matched: &[u32]) {
+        self.best_height.store(height as usize, Ordering::Release);
+        let funding_txos: Vec<_> = self.monitor.funding_txos.lock().unwrap().values().cloned().collect();
+
+        let mut lock = self.state.lock().unwrap();
+        let state = &mut *lock;
+        let mut changed = false;
+        for tx in txn_matched.iter() {
+            let txid = tx.txid().to_hex();
+            for funding_txo in funding_txos.iter() {
+                let spends_funding = tx.input.iter().any(|input| {
+                    input.previous_output.txid == funding_txo.txid
+                        && input.previous_output.vout == funding_txo.index as u32
+                });
+                if !spends_funding {
+                    continue;
+                }
+                let channel_id = funding_txo.to_channel_id();
+                let id = hex_str(&channel_id);
+                if !state.closed.iter().any(|record| record.id == id) {
+                    // confirmed before we noticed the channel going away
+                    let channel = state.open.remove(&channel_id);
+                    let intent = state.intents.remove(&channel_id);
+                    state.closed.push(self.new_record(&channel_id, channel, intent));
+                }
+                let close_type = self.classify(tx);
+                let record = state.closed.iter_mut().find(|record| record.id == id).unwrap();
+                if record.closing_txid.is_none() {
+                    info!("Channel {} closed on chain by {}", &id, &txid);
+                    record.spendable_at_height = match close_type {
+                        // our output is a plain key output, usable right away
+                        CloseType::Cooperative | CloseType::RemoteForce => Some(height),
+                        _ => None,
+                    };
+                    state.confirmed_closes.push((channel_id, close_type.clone(), (*tx).clone()));
+                    self.events.publish(NodeEvent::ChannelClosed {
+                        channel_id: id.clone(),
+                        peer: record.peer.clone(),
+                        close_type: close_type.clone(),
+                        closing_txid: txid.clone(),
+                        height,
+                    });
+                    record.close_type = Some(close_type);
+                    record.closing_txid = Some(txid.clone());
+                    record.closed_at_height = Some(height);
+                    changed = true;
+                }
+            }
+            // penalty transaction confirmed, the whole channel balance is ours now
+            for record in state.closed.iter_mut() {
+                if record.close_type != Some(CloseType::Breach) || record.spendable_at_height.is_some() {
+                    continue;
+                }
+                if let Some(ref closing_txid) = record.closing_txid {
+                    if tx.input.iter().any(|input| input.previous_output.txid.to_hex() == *closing_txid) {
+                        record.spendable_at_height = Some(height);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if changed {
+            self.persist(&state.closed);
+        }
+    }
+
+    fn block_disconnected(&self, _header: &BlockHeader, disconnected_height: u32) {
+        self.best_height.store(disconnected_height.saturating_sub(1) as usize, Ordering::Release);
+        let mut lock = self.state.lock().unwrap();
+        let state = &mut *lock;
+        let mut changed = false;
+        for record in state.closed.iter_mut() {
+            if record.closed_at_height.map_or(false, |height| height >= disconnected_height) {
+                info!("Close of channel {} was reorged out", &record.id);
+                if let Some(closing_txid) = record.closing_txid.clone() {
+                    state.confirmed_closes.retain(|(_, _, tx)| tx.txid().to_hex() != closing_txid);
+                    state.reorged_closes.push((record.id.clone(), closing_txid));
+                }
+                record.closing_txid = None;
+                record.closed_at_height = None;
+                record.spendable_at_height = None;
+                changed = true;
+            } else if record.spendable_at_height.map_or(false, |height| height >= disconnected_height) {
+                record.spendable_at_height = None;
+                changed = true;
+            }
+        }
+        if changed {
+            self.persist(&state.closed);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use bytes::BufMut;
 
 use futures::future;
+use futures::future::AbortHandle;
 use futures::{FutureExt, StreamExt, SinkExt};
 // use futures::task::Poll;
 use futures::channel::mpsc;
@@ -14,17 +15,23 @@ use lightning::ln::peer_handler;
 use lightning::ln::peer_handler::SocketDescriptor as LnSocketTrait;
 
 use std::mem;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
 use std::time::{Duration};
 use std::vec::Vec;
 use std::hash::Hash;
 
+use super::connections::ConnectionRegistry;
+use super::socks5;
 use crate::executor::Larva;
 
 static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+pub const DEFAULT_PORT: u16 = 9735;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// A connection to a remote peer. Can be constructed either as a remote connection using
 /// Connection::setup_outbound o
 pub struct Connection {
@@ -34,74 +41,91 @@ pub struct Connection {
     read_blocker: Option<futures::channel::oneshot::Sender<Result<(), ()>>>,
     read_paused: bool,
     need_disconnect: bool,
+    reader_abort: Option<AbortHandle>,
+    bytes_sent: u64,
+    bytes_received: u64,
     id: u64,
 }
 
 impl Connection {
     fn schedule_read<T: Larva>(
         peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
         this: Arc<Mutex<Self>>,
         reader: futures::stream::SplitStream<tokio_codec::Framed<TcpStream, tokio_codec::BytesCodec>>,
         larva: T
     ) {
+        let id = this.lock().unwrap().id;
         let this_ref = this.clone();
         let this_close_ref = this.clone();
         let peer_manager_ref = peer_manager.clone();
+        let registry_ref = registry.clone();
         let larva_ref = larva.clone();
-        let _ = larva.clone().spawn_task(
-            async move {
-                reader.for_each(move |b| {
-                    let b = b.unwrap();
-                    let pending_read = b.to_vec();
+        let (reader, reader_abort) = future::abortable(reader.for_each(move |b| {
+            let b = b.unwrap();
+            let pending_read = b.to_vec();
 
-                    {
-                        let mut lock = this_ref.lock().unwrap();
-                        assert!(lock.pending_read.is_empty());
-                        if lock.read_paused {
-                            debug!("READ PAUSED");
-                            lock.pending_read = pending_read;
-                            let (sender, blocker) = futures::channel::oneshot::channel();
-                            lock.read_blocker = Some(sender);
-                            return future::Either::Left(blocker.then(|_| { future::ready(()) }));
-                        }
-                    }
+            {
+                let mut lock = this_ref.lock().unwrap();
+                lock.bytes_received += pending_read.len() as u64;
+                assert!(lock.pending_read.is_empty());
+                if lock.read_paused {
+                    debug!("READ PAUSED");
+                    lock.pending_read = pending_read;
+                    let (sender, blocker) = futures::channel::oneshot::channel();
+                    lock.read_blocker = Some(sender);
+                    return future::Either::Left(blocker.then(|_| { future::ready(()) }));
+                }
+            }
 
-                    //TODO: There's a race where we don't meet the requirements of disconnect_socket if its
-                    //called right here, after we release the us_ref lock in the scope above, but before we
-                    //call read_event!
-                    let mut sd = SocketDescriptor::new(
-                        this_ref.clone(),
-                        peer_manager.clone(),
-                        larva.clone()
-                    );
-                    match peer_manager.read_event(&mut sd, pending_read) {
-                        Ok(pause_read) => {
-                            if pause_read {
-                                let mut lock = this_ref.lock().unwrap();
-                                lock.read_paused = true;
-                            }
-                        },
-                        Err(e) => {
-                            error!("Peer Manager Read Error{}", e);
-                            this_ref.lock().unwrap().need_disconnect = false;
-                            // return future::Either::Right(future::ok(std::io::Error::new(std::io::ErrorKind::InvalidData, e)));
-                            // TODO: should discuss Err
-                            return future::Either::Right(future::ready(()));
-                        }
+            //TODO: There's a race where we don't meet the requirements of disconnect_socket if its
+            //called right here, after we release the us_ref lock in the scope above, but before we
+            //call read_event!
+            let mut sd = SocketDescriptor::new(
+                this_ref.clone(),
+                peer_manager.clone(),
+                larva.clone()
+            );
+            let handshake = registry.handshake_lock(id);
+            match peer_manager.read_event(&mut sd, pending_read) {
+                Ok(pause_read) => {
+                    if pause_read {
+                        let mut lock = this_ref.lock().unwrap();
+                        lock.read_paused = true;
                     }
+                },
+                Err(e) => {
+                    error!("Peer Manager Read Error{}", e);
+                    this_ref.lock().unwrap().need_disconnect = false;
+                    // return future::Either::Right(future::ok(std::io::Error::new(std::io::ErrorKind::InvalidData, e)));
+                    // TODO: should discuss Err
+                    return future::Either::Right(future::ready(()));
+                }
+            }
+            if handshake.is_some() {
+                registry.assign_node_id(id, peer_manager.get_peer_node_ids());
+            }
+            drop(handshake);
+            if registry.awaiting_handshake(id) {
+                registry.handshake_done(id, &peer_manager.get_peer_node_ids());
+            }
 
-                    if let Err(e) = this_ref.lock().unwrap().event_notify.try_send(()) {
-                        // Ignore full errors as we just need them to poll after this point, so if the user
-                        // hasn't received the last send yet, it doesn't matter.
-                        assert!(e.is_full());
-                    }
+            if let Err(e) = this_ref.lock().unwrap().event_notify.try_send(()) {
+                // Ignore full errors as we just need them to poll after this point, so if the user
+                // hasn't received the last send yet, it doesn't matter.
+                assert!(e.is_full());
+            }
 
 
-                    // TODO: FYI, this part should be rewrote
-                    return future::Either::Right(future::ready(()));
+            // TODO: FYI, this part should be rewrote
+            return future::Either::Right(future::ready(()));
 
-                })
-                .then(move |_| {
+        }));
+        this.lock().unwrap().reader_abort = Some(reader_abort);
+        let _ = larva.clone().spawn_task(
+            async move {
+                reader.then(move |_| {
+                    registry_ref.remove(id);
                     if this_close_ref.lock().unwrap().need_disconnect {
                         peer_manager_ref.disconnect_event(
                             &SocketDescriptor::new(
@@ -119,6 +143,22 @@ impl Connection {
         );
     }
 
+    /// Closes the connection from our side, PeerManager gets its disconnect_event once the
+    /// reader stopped.
+    pub fn shutdown(this: &Arc<Mutex<Self>>) {
+        let mut us = this.lock().unwrap();
+        us.need_disconnect = true;
+        us.writer = None;
+        if let Some(reader_abort) = us.reader_abort.take() {
+            reader_abort.abort();
+        }
+    }
+
+    /// (bytes sent, bytes received)
+    pub fn traffic(&self) -> (u64, u64) {
+        (self.bytes_sent, self.bytes_received)
+    }
+
     fn new(event_notify: mpsc::Sender<()>, stream: TcpStream, larva: &impl Larva) ->
         (futures::stream::SplitStream<tokio_codec::Framed<TcpStream, tokio_codec::BytesCodec>>, Arc<Mutex<Self>>) {
             let (mut writer, reader) = tokio_codec::Framed::new(stream, tokio_codec::BytesCodec::new()).split();
@@ -144,6 +184,9 @@ impl Connection {
                         read_blocker: None,
                         read_paused: false,
                         need_disconnect: true,
+                        reader_abort: None,
+                        bytes_sent: 0,
+                        bytes_received: 0,
                         id: ID_COUNTER.fetch_add(1, Ordering::AcqRel)
                     }
                 )
@@ -158,92 +201,186 @@ impl Connection {
     /// ChannelManager and ChannelMonitor objects.
     pub fn setup_inbound<T: Larva>(
         peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
         event_notify: mpsc::Sender<()>,
         stream: TcpStream,
         larva: T
     ) {
+        let addr = stream.peer_addr().ok();
         let (reader, this) = Self::new(event_notify, stream, &larva);
 
         if let Ok(_) = peer_manager.new_inbound_connection(SocketDescriptor::new(this.clone(), peer_manager.clone(), larva.clone())) {
-            Self::schedule_read(peer_manager, this, reader, larva);
+            let id = this.lock().unwrap().id;
+            registry.register(id, this.clone(), None, addr, None);
+            Self::schedule_read(peer_manager, registry, this, reader, larva);
         }
     }
 
     /// Process incoming messages and feed outgoing messages on the provided socket generated by
     /// making an outbound connection which is expected to be accepted by a peer with the given
-    /// public key (by scheduling futures with tokio::spawn).
+    /// public key (by scheduling futures with tokio::spawn). `target` is the host:port the proxy
+    /// was asked to connect to, if the socket goes through one.
     ///
     /// You should poll the Receive end of event_notify and call get_and_clear_pending_events() on
     /// ChannelManager and ChannelMonitor objects.
     pub fn setup_outbound<T: Larva> (
         peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
         event_notify: mpsc::Sender<()>,
         their_node_id: PublicKey,
-        stream: TcpStream, larva: T) {
+        stream: TcpStream, target: Option<String>, larva: T) -> Result<(), String> {
+        let addr = match target {
+            Some(_) => None,
+            None => stream.peer_addr().ok(),
+        };
         let (reader, us) = Self::new(event_notify, stream, &larva);
-        if let Ok(initial_send) = peer_manager.new_outbound_connection(
+        match peer_manager.new_outbound_connection(
             their_node_id,
             SocketDescriptor::new(us.clone(), peer_manager.clone(), larva.clone())
         ) {
-            if SocketDescriptor::new(us.clone(), peer_manager.clone(), larva.clone())
-                .send_data(&initial_send, true) == initial_send.len() {
+            Ok(initial_send) => {
+                if SocketDescriptor::new(us.clone(), peer_manager.clone(), larva.clone())
+                    .send_data(&initial_send, true) == initial_send.len() {
 
-                    Self::schedule_read(peer_manager, us, reader, larva);
-                    info!("Outbound Connection Established {}", &their_node_id);
-                } else {
-                    debug!("Failed to write first full message to socket!");
-                }
+                        let id = us.lock().unwrap().id;
+                        registry.register(id, us.clone(), Some(their_node_id), addr, target);
+                        Self::schedule_read(peer_manager, registry, us, reader, larva);
+                        info!("Outbound Connection Established {}", &their_node_id);
+                        Ok(())
+                    } else {
+                        debug!("Failed to write first full message to socket!");
+                        Err("Failed to write first full message to socket".to_string())
+                    }
+            }
+            Err(e) => Err(format!("Failed to set up connection: {:?}", e)),
         }
     }
 
     /// Process incoming messages and feed outgoing messages on a new connection made to the given
     /// socket address which is expected to be accepted by a peer with the given public key (by
-    /// scheduling futures with tokio::spawn).
+    /// scheduling futures with tokio::spawn). Resolves once the first handshake message is sent.
     ///
     /// You should poll the Receive end of event_notify and call get_and_clear_pending_events() on
     /// ChannelManager and ChannelMonitor objects.
-    pub fn connect_outbound<T: Larva>(
+    pub async fn connect_outbound<T: Larva>(
         peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
         event_notify: mpsc::Sender<()>,
         their_node_id: PublicKey,
-        addr: SocketAddr, larva: T) where T: Unpin {
-        let connect_timeout = futures_timer::Delay::new(Duration::from_secs(10)).then(|_| {
-            future::ready(std::io::Error::new(std::io::ErrorKind::TimedOut, "timeout reached"))
-        });
-        let _ = larva.clone().spawn_task(async move {
-            match TcpStream::connect(addr).await {
-                Ok(stream) => {
-                    Connection::setup_outbound(peer_manager, event_notify, their_node_id, stream, larva);
-                    return Ok(());
-                },
+        addr: SocketAddr, larva: T) -> Result<(), String> {
+        let connect = Box::pin(TcpStream::connect(addr));
+        let connect_timeout = Box::pin(futures_timer::Delay::new(CONNECT_TIMEOUT));
+        match future::select(connect, connect_timeout).await {
+            future::Either::Left((Ok(stream), _)) => {
+                Connection::setup_outbound(peer_manager, registry, event_notify, their_node_id, stream, None, larva)
+            }
+            future::Either::Left((Err(e), _)) => {
+                Err(format!("Failed to connect to {}: {}", addr, e))
+            }
+            future::Either::Right(_) => {
+                // dropping the connect future gives up on the socket
+                Err(format!("Timed out connecting to {}", addr))
+            }
+        }
+    }
+
+    /// Like connect_outbound, but going through a SOCKS5 proxy which also resolves the host.
+    pub async fn connect_proxied<T: Larva>(
+        peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
+        event_notify: mpsc::Sender<()>,
+        their_node_id: PublicKey,
+        proxy: SocketAddr, host: String, port: u16, larva: T) -> Result<(), String> {
+        info!("Attempting to connect to {} through {}...", format_host_port(&host, port), proxy);
+        let connect = Box::pin(socks5::connect(proxy, host.clone(), port));
+        let connect_timeout = Box::pin(futures_timer::Delay::new(CONNECT_TIMEOUT));
+        match future::select(connect, connect_timeout).await {
+            future::Either::Left((Ok(stream), _)) => {
+                Connection::setup_outbound(peer_manager, registry, event_notify, their_node_id, stream, Some(format_host_port(&host, port)), larva)
+            }
+            future::Either::Left((Err(e), _)) => Err(e),
+            future::Either::Right(_) => {
+                Err(format!("Timed out connecting to {} through {}", format_host_port(&host, port), proxy))
+            }
+        }
+    }
+
+    /// Resolves host:port (hostname, IPv4 or bracketed IPv6, port defaults to 9735) and connects
+    /// to the first address which accepts, see connect_outbound. With a proxy configured nothing
+    /// is resolved or dialed locally.
+    pub async fn connect_address<T: Larva>(
+        peer_manager: Arc<peer_handler::PeerManager<SocketDescriptor<T>>>,
+        registry: Arc<ConnectionRegistry>,
+        event_notify: mpsc::Sender<()>,
+        their_node_id: PublicKey,
+        address: String, larva: T) -> Result<(), String> {
+        if registry.ban_list().is_banned_node(&their_node_id) {
+            return Err(format!("{} is banned", their_node_id));
+        }
+        let (host, port) = parse_host_port(&address)?;
+        if let Some(proxy) = registry.proxy() {
+            return Connection::connect_proxied(peer_manager, registry, event_notify, their_node_id, proxy, host, port, larva).await;
+        }
+        let mut last_err = format!("No addresses found for {}", &address);
+        for addr in resolve(host, port).await? {
+            info!("Attempting to connect to {}...", addr);
+            match Connection::connect_outbound(peer_manager.clone(), registry.clone(), event_notify.clone(), their_node_id, addr, larva.clone()).await {
+                Ok(()) => return Ok(()),
                 Err(e) => {
-                    // TODO log e
-                    return Err(());
+                    debug!("{}", &e);
+                    last_err = e;
                 }
             }
-        });
-        // let _ = larva.clone().spawn_task(futures::future::select(
-        //     TcpStream::connect(&addr), connect_timeout).then(move |either| {
-        //         match either {
-        //             future::Either::Left((x, _)) => {
-        //                 if let Ok(stream) = x {
-        //                     Connection::setup_outbound(peer_manager, event_notify, their_node_id, stream, larva);
-        //                     return future::ok(());
-        //                 } else {
-        //                     // TODO show err
-        //                     return future::err(());
-        //                 }
-        //             },
-        //             future::Either::Right((_, left)) => {
-        //                 let _ = left.map(|stream| {
-        //                     let stream = stream.unwrap();
-        //                     let _ = stream.shutdown(std::net::Shutdown::Both);
-        //                 });
-        //                 future::err(())
-        //             },
-        //         }
-        //     }));
+        }
+        Err(last_err)
+    }
+}
+
+/// Splits host[:port] into host and port, IPv6 literals may be given bare or in brackets
+pub fn parse_host_port(address: &str) -> Result<(String, u16), String> {
+    let parse_port = |port: &str| -> Result<u16, String> {
+        port.parse().map_err(|_| format!("Invalid port in {}", address))
+    };
+    if address.starts_with('[') {
+        let end = address.find(']').ok_or(format!("Unterminated IPv6 address {}", address))?;
+        let host = address[1..end].to_string();
+        match &address[end + 1..] {
+            "" => Ok((host, DEFAULT_PORT)),
+            rest if rest.starts_with(':') => Ok((host, parse_port(&rest[1..])?)),
+            _ => Err(format!("Invalid address {}", address)),
+        }
+    } else if address.matches(':').count() > 1 {
+        Ok((address.to_string(), DEFAULT_PORT))
+    } else {
+        match address.rfind(':') {
+            Some(idx) => Ok((address[..idx].to_string(), parse_port(&address[idx + 1..])?)),
+            None => Ok((address.to_string(), DEFAULT_PORT)),
+        }
+    }
+}
+
+/// Formats host and port back into host:port, bracketing IPv6 literals
+pub fn format_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Resolves a host without blocking the executor, lookups run on their own thread
+pub async fn resolve(host: String, port: u16) -> Result<Vec<SocketAddr>, String> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
     }
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    thread::spawn(move || {
+        let res = (host.as_str(), port).to_socket_addrs()
+            .map(|addrs| addrs.collect())
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e));
+        let _ = sender.send(res);
+    });
+    receiver.await.map_err(|_| "Name resolution aborted".to_string())?
 }
 
 #[derive(Clone)]
@@ -336,6 +473,7 @@ impl<T: Larva> peer_handler::SocketDescriptor for SocketDescriptor<T> {
         // TODO check logic
         match writer.clone().try_send(bytes.freeze()) {
             Ok(_) => {
+                us.bytes_sent += data.len() as u64;
                 data.len()
             },
             Err(e) => {
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use secp256k1::key::PublicKey;
+
+use protocol::NodeEvent;
+
+use super::ban_list::BanList;
+use super::connection::Connection;
+use super::events::EventBus;
+use super::utils::hex_str;
+
+struct Entry {
+    conn: Arc<Mutex<Connection>>,
+    node_id: Option<PublicKey>,
+    addr: Option<SocketAddr>,
+    target: Option<String>,
+    inbound: bool,
+    connected_since: SystemTime,
+    // handshake completed and PeerConnected published
+    connected: bool,
+}
+
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub node_id: Option<PublicKey>,
+    // remote socket address, None for proxied connections where it would be the proxy
+    pub addr: Option<SocketAddr>,
+    // host:port we asked the proxy to connect to
+    pub target: Option<String>,
+    pub inbound: bool,
+    pub connected_since: SystemTime,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Live connections by connection id. PeerManager doesn't tell us which socket belongs to which
+/// node, outbound connections know it upfront, inbound ones get it assigned once their
+/// handshake completes (see Connection::schedule_read).
+pub struct ConnectionRegistry {
+    conns: Mutex<HashMap<u64, Entry>>,
+    // serializes handshake reads so a newly appeared node id belongs to the connection just read
+    handshake: Mutex<()>,
+    ban_list: Arc<BanList>,
+    // outbound connections go through this SOCKS5 proxy when set
+    proxy: Option<SocketAddr>,
+    events: Arc<EventBus>,
+}
+
+impl ConnectionRegistry {
+    pub fn new(ban_list: Arc<BanList>, proxy: Option<SocketAddr>, events: Arc<EventBus>) -> Self {
+        Self {
+            conns: Mutex::new(HashMap::new()),
+            handshake: Mutex::new(()),
+            ban_list,
+            proxy,
+            events,
+        }
+    }
+
+    pub fn ban_list(&self) -> &Arc<BanList> {
+        &self.ban_list
+    }
+
+    pub fn proxy(&self) -> Option<SocketAddr> {
+        self.proxy
+    }
+
+    /// Inbound connections are registered without a node id
+    pub fn register(&self, id: u64, conn: Arc<Mutex<Connection>>, node_id: Option<PublicKey>, addr: Option<SocketAddr>, target: Option<String>) {
+        self.conns.lock().unwrap().insert(id, Entry {
+            conn,
+            node_id,
+            addr,
+            target,
+            inbound: node_id.is_none(),
+            connected_since: SystemTime::now(),
+            connected: false,
+        });
+    }
+
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        let entries: Vec<(Arc<Mutex<Connection>>, ConnectionInfo)> = self.conns.lock().unwrap().iter()
+            .map(|(id, entry)| (entry.conn.clone(), ConnectionInfo {
+                id: *id,
+                node_id: entry.node_id,
+                addr: entry.addr,
+                target: entry.target.clone(),
+                inbound: entry.inbound,
+                connected_since: entry.connected_since,
+                bytes_sent: 0,
+                bytes_received: 0,
+            }))
+            .collect();
+        // traffic is read after releasing the registry, connections are locked on their own
+        let mut infos: Vec<ConnectionInfo> = entries.into_iter().map(|(conn, mut info)| {
+            let (bytes_sent, bytes_received) = conn.lock().unwrap().traffic();
+            info.bytes_sent = bytes_sent;
+            info.bytes_received = bytes_received;
+            info
+        }).collect();
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    pub fn remove(&self, id: u64) {
+        let entry = self.conns.lock().unwrap().remove(&id);
+        if let Some(Entry { node_id: Some(node_id), connected: true, .. }) = entry {
+            self.events.publish(NodeEvent::PeerDisconnected { node_id: hex_str(&node_id.serialize()) });
+        }
+    }
+
+    /// True until the connection's peer completed its handshake
+    pub fn awaiting_handshake(&self, id: u64) -> bool {
+        self.conns.lock().unwrap().get(&id).map_or(false, |entry| !entry.connected)
+    }
+
+    /// Publishes PeerConnected once the node id of `id` shows up among the peers PeerManager
+    /// finished the handshake with
+    pub fn handshake_done(&self, id: u64, peer_node_ids: &[PublicKey]) {
+        let node_id = {
+            let mut conns = self.conns.lock().unwrap();
+            match conns.get_mut(&id) {
+                Some(entry) if !entry.connected => match entry.node_id {
+                    Some(node_id) if peer_node_ids.contains(&node_id) => {
+                        entry.connected = true;
+                        node_id
+                    }
+                    _ => return,
+                },
+                _ => return,
+            }
+        };
+        self.events.publish(NodeEvent::PeerConnected { node_id: hex_str(&node_id.serialize()) });
+    }
+
+    /// Held around read_event() while a connection has no node id yet
+    pub fn handshake_lock(&self, id: u64) -> Option<MutexGuard<()>> {
+        let needs_node_id = self.conns.lock().unwrap().get(&id).map_or(false, |entry| entry.node_id.is_none());
+        if needs_node_id {
+            Some(self.handshake.lock().unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Assigns the one connected node id not yet owned by any connection to `id`, disconnects
+    /// it right away if banned.
+    pub fn assign_node_id(&self, id: u64, peer_node_ids: Vec<PublicKey>) {
+        let banned = {
+            let mut conns = self.conns.lock().unwrap();
+            let known: HashSet<PublicKey> = conns.values().filter_map(|entry| entry.node_id).collect();
+            let node_id = match peer_node_ids.into_iter().find(|node_id| !known.contains(node_id)) {
+                Some(node_id) => node_id,
+                None => return,
+            };
+            match conns.get_mut(&id) {
+                Some(entry) => {
+                    entry.node_id = Some(node_id);
+                    self.ban_list.is_banned_node(&node_id)
+                }
+                None => return,
+            }
+        };
+        if banned {
+            info!("Dropping banned peer on connection {}", id);
+            let conn = self.conns.lock().unwrap().get(&id).map(|entry| entry.conn.clone());
+            if let Some(conn) = conn {
+                Connection::shutdown(&conn);
+            }
+        }
+    }
+
+    /// Disconnects every connection to the node, false if there was none
+    pub fn disconnect_node(&self, node_id: &PublicKey) -> bool {
+        self.disconnect_where(|entry| entry.node_id.as_ref() == Some(node_id))
+    }
+
+    /// Disconnects every connection from the given IP
+    pub fn disconnect_ip(&self, ip: &IpAddr) -> bool {
+        self.disconnect_where(|entry| entry.addr.map(|addr| addr.ip()) == Some(*ip))
+    }
+
+    fn disconnect_where<F: Fn(&Entry) -> bool>(&self, filter: F) -> bool {
+        // collected first, shutdown locks the connection
+        let conns: Vec<Arc<Mutex<Connection>>> = self.conns.lock().unwrap().values()
+            .filter(|entry| filter(entry))
+            .map(|entry| entry.conn.clone())
+            .collect();
+        for conn in conns.iter() {
+            Connection::shutdown(conn);
+        }
+        !conns.is_empty()
+    }
+}
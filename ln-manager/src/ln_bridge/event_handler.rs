@@ -19,13 +19,18 @@ use lightning::ln::channelmonitor;
 use lightning::ln::peer_handler;
 use lightning::util::events::{Event, EventsProvider};
 use lightning::util::ser::Writeable; 
+use bitcoin_hashes::Hash;
+use protocol::NodeEvent;
 use super::connection::SocketDescriptor;
+use super::closed_channels::ClosedChannels;
+use super::close_addresses::{CloseAddresses, PendingSweep};
+use super::events::EventBus;
 
 use super::utils::{hex_to_vec, hex_str};
 use super::rpc_client::RPCClient;
 use crate::executor::Larva;
 use crate::utils::{compact_btc_to_bech32};
-use log::{info};
+use log::{info, error};
 
 async fn handle_fund_tx<T: Larva>(
     mut self_sender: mpsc::Sender<()>,
@@ -70,6 +75,47 @@ async fn handle_fund_tx<T: Larva>(
     info!("Generated funding tx!");
 }
 
+// Moves our output of a confirmed cooperative close from the shutdown key to the close address
+fn btc_amount(sats: u64) -> String {
+    format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000)
+}
+
+fn json_str<'a>(value: &'a serde_json::Value, method: &str) -> Result<&'a str, String> {
+    value.as_str().ok_or(format!("{} returned no hex", method))
+}
+
+async fn sweep_close_output<T: Larva>(this: &Arc<EventHandler<T>>, sweep: &PendingSweep) -> Result<(), String> {
+    let raw_tx_args = &[
+        &format!("[{{\"txid\": \"{}\", \"vout\": {}}}]", sweep.txid, sweep.vout)[..],
+        &format!("{{\"{}\": {}}}", sweep.address, btc_amount(sweep.value)),
+    ];
+    let tx_hex = this.rpc_client.make_rpc_call("createrawtransaction", raw_tx_args, false).await
+        .map_err(|_| "createrawtransaction failed".to_string())?;
+
+    // the fee comes out of the swept amount, no wallet inputs or change
+    let funded_tx_args = &[
+        &format!("\"{}\"", json_str(&tx_hex, "createrawtransaction")?)[..],
+        "{\"subtractFeeFromOutputs\": [0]}",
+    ];
+    let funded_tx = this.rpc_client.make_rpc_call("fundrawtransaction", funded_tx_args, false).await
+        .map_err(|_| "fundrawtransaction failed".to_string())?;
+
+    let signed_tx_args = &[&format!("\"{}\"", json_str(&funded_tx["hex"], "fundrawtransaction")?)[..]];
+    let signed_tx = this.rpc_client.make_rpc_call("signrawtransactionwithwallet", signed_tx_args, false).await
+        .map_err(|_| "signrawtransactionwithwallet failed".to_string())?;
+    if !signed_tx["complete"].as_bool().unwrap_or(false) {
+        return Err("signrawtransactionwithwallet could not sign the sweep".to_string());
+    }
+
+    let tx_bytes = hex_to_vec(json_str(&signed_tx["hex"], "signrawtransactionwithwallet")?)
+        .ok_or("signrawtransactionwithwallet returned invalid hex".to_string())?;
+    let tx: blockdata::transaction::Transaction = encode::deserialize(&tx_bytes)
+        .map_err(|e| format!("Invalid signed sweep transaction: {}", e))?;
+    this.broadcaster.broadcast_transaction(&tx);
+    info!("Swept close of channel {} to {} in {}", sweep.channel_id, sweep.address, tx.txid());
+    Ok(())
+}
+
 async fn handle_events<T: Larva>(
     this: &Arc<EventHandler<T>>,
     self_sender: &mpsc::Sender<()>,
@@ -97,6 +143,10 @@ async fn handle_events<T: Larva>(
                 if let Some(payment_preimage) = images.get(&payment_hash) {
                     if this.channel_manager.claim_funds(payment_preimage.clone()) {
                         info!("Payment received: {} msat id {}", amt, hex_str(&payment_hash.0));
+                        this.events.publish(NodeEvent::PaymentReceived {
+                            payment_hash: hex_str(&payment_hash.0),
+                            amount_msat: amt,
+                        });
                     } else {
                         info!("Failed to claim money we were told we had?");
                     }
@@ -122,29 +172,70 @@ async fn handle_events<T: Larva>(
                 let tx = txn.remove(&funding_txo).unwrap();
                 this.broadcaster.broadcast_transaction(&tx);
                 info!("Broadcast funding tx {}!", tx.txid());
+                this.events.publish(NodeEvent::FundingBroadcast {
+                    funding_txo: format!("{}:{}", funding_txo.txid, funding_txo.index),
+                });
             },
             Event::PaymentSent { payment_preimage } => {
                 info!("Payment Sent, proof: {}", hex_str(&payment_preimage.0));
+                let payment_hash = bitcoin_hashes::sha256::Hash::hash(&payment_preimage.0);
+                this.events.publish(NodeEvent::PaymentSent {
+                    payment_hash: hex_str(&payment_hash.into_inner()),
+                    payment_preimage: hex_str(&payment_preimage.0),
+                });
             },
             Event::PaymentFailed { payment_hash, rejected_by_dest } => {
                 info!("{} failed id {}!", if rejected_by_dest { "Send" } else { "Route" }, hex_str(&payment_hash.0));
+                this.events.publish(NodeEvent::PaymentFailed {
+                    payment_hash: hex_str(&payment_hash.0),
+                    rejected_by_dest,
+                });
             },
             Event::SpendableOutputs { mut outputs } => {
+                let mut outpoints = Vec::with_capacity(outputs.len());
                 for output in outputs.drain(..) {
                     match output {
                         SpendableOutputDescriptor:: StaticOutput { outpoint, .. } => {
                             info!("Got on-chain output Bitcoin Core should know how to claim at {}:{}", hex_str(&outpoint.txid[..]), outpoint.vout);
+                            outpoints.push(outpoint);
                         },
-                        SpendableOutputDescriptor::DynamicOutputP2WSH { .. } => {
+                        SpendableOutputDescriptor::DynamicOutputP2WSH { outpoint, .. } => {
                             info!("Got on-chain output we should claim...");
                             //TODO: Send back to Bitcoin Core!
+                            outpoints.push(outpoint);
                         },
-                        SpendableOutputDescriptor::DynamicOutputP2WPKH { .. } => {
+                        SpendableOutputDescriptor::DynamicOutputP2WPKH { outpoint, .. } => {
                             info!("Got on-chain output we should claim...");
                             //TODO: Send back to Bitcoin Core!
+                            outpoints.push(outpoint);
                         },
                     }
                 }
+                let txids: Vec<_> = outpoints.iter().map(|outpoint| outpoint.txid).collect();
+                this.closed_channels.spendable_outputs(&txids);
+                this.events.publish(NodeEvent::SpendableOutputs {
+                    outpoints: outpoints.iter().map(|outpoint| format!("{}:{}", outpoint.txid, outpoint.vout)).collect(),
+                });
+            }
+        }
+    }
+
+    this.closed_channels.update(&this.channel_manager);
+    this.close_addresses.update(&this.channel_manager.list_channels());
+    for (channel_id, closing_txid) in this.closed_channels.take_reorged_closes() {
+        this.close_addresses.unqueue_sweep(&channel_id, &closing_txid);
+    }
+    for (channel_id, close_type, closing_tx) in this.closed_channels.take_confirmed_closes() {
+        this.close_addresses.queue_sweep(&channel_id, &close_type, &closing_tx);
+    }
+    // failed sweeps stay queued in lndata and are retried on the next block
+    let height = this.closed_channels.best_height();
+    for sweep in this.close_addresses.due_sweeps(height) {
+        match sweep_close_output(this, &sweep).await {
+            Ok(()) => this.close_addresses.sweep_done(&sweep.txid, sweep.vout),
+            Err(e) => {
+                error!("Failed to sweep close of channel {} to {}: {}", sweep.channel_id, sweep.address, e);
+                this.close_addresses.sweep_failed(&sweep.txid, sweep.vout, height);
             }
         }
     }
@@ -169,6 +260,9 @@ pub struct EventHandler<T: Larva> {
     broadcaster: Arc<dyn chain::chaininterface::BroadcasterInterface>,
     txn_to_broadcast: Mutex<HashMap<chain::transaction::OutPoint, blockdata::transaction::Transaction>>,
     payment_preimages: Arc<Mutex<HashMap<PaymentHash, PaymentPreimage>>>,
+    closed_channels: Arc<ClosedChannels<T>>,
+    close_addresses: Arc<CloseAddresses>,
+    events: Arc<EventBus>,
 }
 
 impl<T: Larva> EventHandler<T> {
@@ -181,6 +275,9 @@ impl<T: Larva> EventHandler<T> {
         channel_manager: Arc<channelmanager::ChannelManager>,
         broadcaster: Arc<dyn chain::chaininterface::BroadcasterInterface>,
         payment_preimages: Arc<Mutex<HashMap<PaymentHash, PaymentPreimage>>>,
+        closed_channels: Arc<ClosedChannels<T>>,
+        close_addresses: Arc<CloseAddresses>,
+        events: Arc<EventBus>,
         larva: impl Larva,
     ) -> mpsc::Sender<()> {
         let this = Arc::new(Self {
@@ -193,6 +290,9 @@ impl<T: Larva> EventHandler<T> {
             broadcaster,
             txn_to_broadcast: Mutex::new(HashMap::new()),
             payment_preimages,
+            closed_channels,
+            close_addresses,
+            events,
         });
         let (sender, receiver) = mpsc::channel(2);
         let self_sender = sender.clone();
@@ -0,0 +1,41 @@
+use std::sync::Mutex;
+
+use futures::channel::mpsc;
+use protocol::NodeEvent;
+
+// events buffered per subscriber before it is considered stuck
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+/// Republishes node events to every subscriber. A subscriber that doesn't keep up is dropped
+/// rather than holding up the node, its stream ends and it has to subscribe again.
+pub struct EventBus {
+    subscribers: Mutex<Vec<mpsc::Sender<NodeEvent>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self { subscribers: Mutex::new(Vec::new()) }
+    }
+
+    pub fn subscribe(&self) -> mpsc::Receiver<NodeEvent> {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: NodeEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let live = subscribers.drain(..).filter_map(|mut subscriber| {
+            match subscriber.try_send(event.clone()) {
+                Ok(()) => Some(subscriber),
+                Err(e) => {
+                    if e.is_full() {
+                        warn!("Dropping event subscriber that fell {} events behind", SUBSCRIBER_BUFFER);
+                    }
+                    None
+                }
+            }
+        }).collect();
+        *subscribers = live;
+    }
+}
@@ -3,11 +3,16 @@ pub mod rpc_client;
 pub mod connection;
 pub mod chain_monitor;
 pub mod channel_manager;
+pub mod router;
 pub mod channel_monitor;
 pub mod event_handler;
+pub mod events;
+pub mod webhooks;
 pub mod utils;
 pub mod log_printer;
 pub mod settings;
+pub mod storage;
+pub mod closed_channels;
 
 pub trait Restorable<R, T> {
   fn try_restore(args: R) -> T;
@@ -20,3 +25,11 @@ pub trait Restorable<R, T> {
 //     RestoreArgs { data_path }
 //   }
 // }
+pub mod close_addresses;
+pub mod channel_labels;
+pub mod peer_store;
+pub mod ban_list;
+pub mod connections;
+pub mod socks5;
+pub mod net_address;
+pub mod node_announcement;
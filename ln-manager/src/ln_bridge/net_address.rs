@@ -0,0 +1,102 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use lightning::ln::msgs::NetAddress;
+
+use super::connection;
+
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+// RFC 4648 base32 without padding, as used by onion addresses
+fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c.to_ascii_lowercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+/// Parses <v2 or v3 onion>.onion[:port] into the address we announce
+pub fn parse_onion(address: &str) -> Result<NetAddress, String> {
+    let (host, port) = connection::parse_host_port(address)?;
+    let invalid = || format!("Invalid onion address: {}", address);
+    if !host.ends_with(".onion") {
+        return Err(invalid());
+    }
+    let encoded = &host[..host.len() - ".onion".len()];
+    let decoded = base32_decode(encoded).ok_or_else(invalid)?;
+    match (encoded.len(), decoded.len()) {
+        (16, 10) => {
+            let mut addr = [0u8; 10];
+            addr.copy_from_slice(&decoded);
+            Ok(NetAddress::OnionV2 { addr, port })
+        }
+        (56, 35) => {
+            let mut ed25519_pubkey = [0u8; 32];
+            ed25519_pubkey.copy_from_slice(&decoded[..32]);
+            Ok(NetAddress::OnionV3 {
+                ed25519_pubkey,
+                checksum: (decoded[32] as u16) << 8 | decoded[33] as u16,
+                version: decoded[34],
+                port,
+            })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses an ip:port (port defaults to 9735) or onion address
+pub fn parse_net_address(address: &str) -> Result<NetAddress, String> {
+    let (host, port) = connection::parse_host_port(address)?;
+    if host.ends_with(".onion") {
+        return parse_onion(address);
+    }
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => Ok(NetAddress::IPv4 { addr: ip.octets(), port }),
+        Ok(IpAddr::V6(ip)) => Ok(NetAddress::IPv6 { addr: ip.octets(), port }),
+        Err(_) => Err(format!("Announced addresses must be IPs or onions: {}", address)),
+    }
+}
+
+/// Formats an announced address as host:port
+pub fn format_net_address(address: &NetAddress) -> String {
+    match address {
+        NetAddress::IPv4 { addr, port } => connection::format_host_port(&Ipv4Addr::from(*addr).to_string(), *port),
+        NetAddress::IPv6 { addr, port } => connection::format_host_port(&Ipv6Addr::from(*addr).to_string(), *port),
+        NetAddress::OnionV2 { addr, port } => format!("{}.onion:{}", base32_encode(addr), port),
+        NetAddress::OnionV3 { ed25519_pubkey, checksum, version, port } => {
+            let mut data = ed25519_pubkey.to_vec();
+            data.push((*checksum >> 8) as u8);
+            data.push(*checksum as u8);
+            data.push(*version);
+            format!("{}.onion:{}", base32_encode(&data), port)
+        }
+    }
+}
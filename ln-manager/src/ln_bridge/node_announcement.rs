@@ -0,0 +1,125 @@
+use std::cmp;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitcoin_hashes::sha256d::Hash as Sha256dHash;
+use bitcoin_hashes::Hash;
+use futures::{future, StreamExt};
+use futures_timer::Interval;
+use secp256k1::key::{PublicKey, SecretKey};
+use secp256k1::{Message, Secp256k1};
+
+use lightning::ln::msgs::{GlobalFeatures, NetAddress, NodeAnnouncement, RoutingMessageHandler, UnsignedNodeAnnouncement};
+use lightning::ln::router::Router;
+use lightning::util::ser::Writeable;
+
+use super::net_address::parse_net_address;
+use super::settings::Node;
+use super::utils::hex_to_vec;
+
+// re-signed with a fresh timestamp so the graph doesn't prune us
+const REANNOUNCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// retries while the router refuses us double up to this
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+// BOLT 7 wants addresses ordered by type
+fn address_type(address: &NetAddress) -> u8 {
+    match address {
+        NetAddress::IPv4 { .. } => 1,
+        NetAddress::IPv6 { .. } => 2,
+        NetAddress::OnionV2 { .. } => 3,
+        NetAddress::OnionV3 { .. } => 4,
+    }
+}
+
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    if color.is_empty() {
+        return Ok([0; 3]);
+    }
+    let hex = color.trim_start_matches('#');
+    match hex_to_vec(hex) {
+        Some(ref rgb) if hex.len() == 6 => Ok([rgb[0], rgb[1], rgb[2]]),
+        _ => Err(format!("Invalid node color {}, expected #rrggbb", color)),
+    }
+}
+
+fn parse_alias(alias: &str) -> Result<[u8; 32], String> {
+    if alias.len() > 32 {
+        return Err(format!("Node alias {} is longer than 32 bytes", alias));
+    }
+    let mut bytes = [0u8; 32];
+    bytes[..alias.len()].copy_from_slice(alias.as_bytes());
+    Ok(bytes)
+}
+
+/// Signed node_announcement built from the [node] settings
+pub struct NodeAnnouncer {
+    node_secret: SecretKey,
+    rgb: [u8; 3],
+    alias: [u8; 32],
+    addresses: Vec<NetAddress>,
+}
+
+impl NodeAnnouncer {
+    pub fn new(node_secret: SecretKey, node: &Node, onion_address: Option<NetAddress>) -> Result<Self, String> {
+        let mut addresses = node.addresses.iter()
+            .map(|address| parse_net_address(address))
+            .collect::<Result<Vec<NetAddress>, String>>()?;
+        addresses.extend(onion_address);
+        addresses.sort_by_key(address_type);
+        Ok(Self {
+            node_secret,
+            rgb: parse_color(&node.color)?,
+            alias: parse_alias(&node.alias)?,
+            addresses,
+        })
+    }
+
+    pub fn sign(&self) -> NodeAnnouncement {
+        let secp_ctx = Secp256k1::signing_only();
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as u32);
+        let contents = UnsignedNodeAnnouncement {
+            features: GlobalFeatures::new(),
+            timestamp,
+            node_id: PublicKey::from_secret_key(&secp_ctx, &self.node_secret),
+            rgb: self.rgb,
+            alias: self.alias,
+            addresses: self.addresses.clone(),
+            excess_address_data: Vec::new(),
+            excess_data: Vec::new(),
+        };
+        let msg_hash = Sha256dHash::hash(&contents.encode()[..]);
+        let signature = secp_ctx.sign(&Message::from_slice(&msg_hash[..]).unwrap(), &self.node_secret);
+        NodeAnnouncement { signature, contents }
+    }
+}
+
+/// Puts our node_announcement into the router's graph once it knows one of our public channels
+/// (it refuses announcements for nodes without channels, retried with backoff) and refreshes it
+/// daily. Peers get it from there on their initial routing sync, rust-lightning can't push our
+/// own announcement to connected peers yet.
+pub async fn spawn_node_announcer(announcer: NodeAnnouncer, router: Arc<Router>) {
+    let mut next_attempt = Instant::now();
+    let mut retry_interval = MIN_RETRY_INTERVAL;
+    Interval::new(MIN_RETRY_INTERVAL)
+        .for_each(|_| {
+            if Instant::now() >= next_attempt {
+                match router.handle_node_announcement(&announcer.sign()) {
+                    Ok(_) => {
+                        info!("Announced our node to the network graph");
+                        next_attempt = Instant::now() + REANNOUNCE_INTERVAL;
+                        retry_interval = MIN_RETRY_INTERVAL;
+                    }
+                    // no public channel yet
+                    Err(e) => {
+                        debug!("Node announcement not accepted yet, retrying in {}s: {}", retry_interval.as_secs(), e.err);
+                        next_attempt = Instant::now() + retry_interval;
+                        retry_interval = cmp::min(retry_interval * 2, MAX_RETRY_INTERVAL);
+                    }
+                }
+            }
+            future::ready(())
+        })
+        .await;
+}
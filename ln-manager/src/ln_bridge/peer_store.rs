@@ -0,0 +1,229 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc;
+use futures::{future, StreamExt};
+use futures_timer::Interval;
+use secp256k1::key::PublicKey;
+
+use lightning::ln::channelmanager::ChannelManager;
+use lightning::ln::msgs::{NetAddress, RoutingMessageHandler};
+use lightning::ln::peer_handler::PeerManager;
+use lightning::ln::router::Router;
+
+use super::connection::{self, Connection, SocketDescriptor};
+use super::connections::ConnectionRegistry;
+use super::net_address::format_net_address;
+use super::storage;
+use super::utils::hex_str;
+use crate::executor::Larva;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+struct Backoff {
+    delay: Duration,
+    next_attempt: Instant,
+    connecting: bool,
+}
+
+/// Address book of peers we connected to, persisted in lndata as node id -> address, along with
+/// the peers disconnected on request
+pub struct PeerStore {
+    filename: String,
+    addresses: Mutex<HashMap<String, String>>,
+    backoff: Mutex<HashMap<PublicKey, Backoff>>,
+    suspended_filename: String,
+    // disconnected on request, not reconnected until `peer connect` or unban (hex node ids)
+    suspended: Mutex<HashSet<String>>,
+}
+
+impl PeerStore {
+    pub fn new(data_path: &str) -> Self {
+        let filename = format!("{}/peers", data_path);
+        let suspended_filename = format!("{}/disconnected_peers", data_path);
+        Self {
+            addresses: Mutex::new(storage::read_json(&filename)),
+            backoff: Mutex::new(HashMap::new()),
+            suspended: Mutex::new(storage::read_json(&suspended_filename)),
+            filename,
+            suspended_filename,
+        }
+    }
+
+    /// Records host:port, hostnames are kept unresolved so reconnects follow DNS changes
+    pub fn add(&self, node_id: &PublicKey, addr: String) {
+        let mut addresses = self.addresses.lock().unwrap();
+        if addresses.insert(hex_str(&node_id.serialize()), addr.clone()).as_ref() != Some(&addr) {
+            if let Err(e) = storage::write_json(&self.filename, &*addresses) {
+                error!("Failed to write peer address book: {}", e);
+            }
+        }
+    }
+
+    pub fn get(&self, node_id: &PublicKey) -> Option<String> {
+        self.addresses.lock().unwrap().get(&hex_str(&node_id.serialize())).cloned()
+    }
+
+    /// Drops the addresses of banned peers, by node id or by the IP they were reached at
+    pub fn forget(&self, node_id: Option<&PublicKey>, ip: Option<&IpAddr>) {
+        let mut addresses = self.addresses.lock().unwrap();
+        let before = addresses.len();
+        addresses.retain(|stored_id, addr| {
+            let same_node = node_id.map_or(false, |node_id| *stored_id == hex_str(&node_id.serialize()));
+            let same_ip = ip.map_or(false, |ip| {
+                connection::parse_host_port(addr).ok()
+                    .and_then(|(host, _)| host.parse::<IpAddr>().ok())
+                    .map_or(false, |host| host == *ip)
+            });
+            !same_node && !same_ip
+        });
+        if addresses.len() != before {
+            if let Err(e) = storage::write_json(&self.filename, &*addresses) {
+                error!("Failed to write peer address book: {}", e);
+            }
+        }
+        if let Some(node_id) = node_id {
+            self.backoff.lock().unwrap().remove(node_id);
+        }
+    }
+
+    pub fn suspend(&self, node_id: &PublicKey) {
+        let mut suspended = self.suspended.lock().unwrap();
+        if suspended.insert(hex_str(&node_id.serialize())) {
+            self.persist_suspended(&suspended);
+        }
+        self.backoff.lock().unwrap().remove(node_id);
+    }
+
+    pub fn resume(&self, node_id: &PublicKey) {
+        let mut suspended = self.suspended.lock().unwrap();
+        if suspended.remove(&hex_str(&node_id.serialize())) {
+            self.persist_suspended(&suspended);
+        }
+    }
+
+    fn is_suspended(&self, node_id: &PublicKey) -> bool {
+        self.suspended.lock().unwrap().contains(&hex_str(&node_id.serialize()))
+    }
+
+    fn persist_suspended(&self, suspended: &HashSet<String>) {
+        if let Err(e) = storage::write_json(&self.suspended_filename, suspended) {
+            error!("Failed to write disconnected peers: {}", e);
+        }
+    }
+
+    // true if a reconnect is due, marks the peer as connecting
+    fn start_attempt(&self, node_id: &PublicKey) -> bool {
+        let now = Instant::now();
+        let mut backoff = self.backoff.lock().unwrap();
+        let entry = backoff.entry(*node_id).or_insert(Backoff {
+            delay: MIN_BACKOFF,
+            next_attempt: now,
+            connecting: false,
+        });
+        if entry.connecting || entry.next_attempt > now {
+            return false;
+        }
+        entry.connecting = true;
+        true
+    }
+
+    // schedules the next attempt, cleared once the peer shows up as connected
+    fn attempt_finished(&self, node_id: &PublicKey) {
+        let mut backoff = self.backoff.lock().unwrap();
+        if let Some(entry) = backoff.get_mut(node_id) {
+            entry.connecting = false;
+            entry.next_attempt = Instant::now() + entry.delay;
+            entry.delay = cmp::min(entry.delay * 2, MAX_BACKOFF);
+        }
+    }
+
+    fn connected(&self, node_id: &PublicKey) {
+        self.backoff.lock().unwrap().remove(node_id);
+    }
+}
+
+// First address from the peer's node_announcement we can dial, onion ones need the proxy
+fn announced_address(router: &Router, node_id: &PublicKey, via_proxy: bool) -> Option<String> {
+    let mut starting_point = None;
+    loop {
+        let batch = router.get_next_node_announcements(starting_point.as_ref(), 255);
+        if batch.is_empty() {
+            return None;
+        }
+        if let Some(announcement) = batch.iter().find(|announcement| announcement.contents.node_id == *node_id) {
+            return announcement.contents.addresses.iter()
+                .find(|address| match address {
+                    NetAddress::IPv4 { .. } | NetAddress::IPv6 { .. } => true,
+                    NetAddress::OnionV2 { .. } | NetAddress::OnionV3 { .. } => via_proxy,
+                })
+                .map(format_net_address);
+        }
+        starting_point = batch.last().map(|announcement| announcement.contents.node_id);
+    }
+}
+
+/// Keeps a connection to every peer we have a channel with, retrying with exponential backoff.
+/// Peers we never connected to ourselves are dialed at their announced address.
+pub async fn spawn_reconnect_supervisor<T: Larva>(
+    peer_store: Arc<PeerStore>,
+    peer_manager: Arc<PeerManager<SocketDescriptor<T>>>,
+    registry: Arc<ConnectionRegistry>,
+    channel_manager: Arc<ChannelManager>,
+    router: Arc<Router>,
+    event_notify: mpsc::Sender<()>,
+    larva: T,
+) {
+    Interval::new(Duration::from_secs(1))
+        .for_each(|_| {
+            let connected: HashSet<PublicKey> = peer_manager.get_peer_node_ids().into_iter().collect();
+            let wanted: HashSet<PublicKey> = channel_manager
+                .list_channels()
+                .into_iter()
+                .map(|channel| channel.remote_network_id)
+                .collect();
+
+            for node_id in wanted.iter() {
+                if connected.contains(node_id) {
+                    peer_store.connected(node_id);
+                    continue;
+                }
+                if registry.ban_list().is_banned_node(node_id) || peer_store.is_suspended(node_id) {
+                    continue;
+                }
+                if !peer_store.start_attempt(node_id) {
+                    continue;
+                }
+                let addr = peer_store.get(node_id)
+                    .or_else(|| announced_address(&router, node_id, registry.proxy().is_some()));
+                let addr = match addr {
+                    Some(addr) => addr,
+                    None => {
+                        // backs off like a failed attempt, the announcement may still show up
+                        warn!("Can't reconnect to {}, no stored or announced address", node_id);
+                        peer_store.attempt_finished(node_id);
+                        continue;
+                    }
+                };
+                info!("Reconnecting to {}@{}...", node_id, addr);
+                let node_id = *node_id;
+                let peer_store = peer_store.clone();
+                let peer_manager = peer_manager.clone();
+                let registry = registry.clone();
+                let event_notify = event_notify.clone();
+                let task_larva = larva.clone();
+                let _ = larva.spawn_task(async move {
+                    let res = Connection::connect_address(peer_manager, registry, event_notify, node_id, addr, task_larva).await;
+                    // the handshake may still fail after connecting, we find out on the next tick
+                    peer_store.attempt_finished(&node_id);
+                    res.map_err(|e| debug!("Reconnect to {} failed: {}", node_id, e))
+                });
+            }
+            future::ready(())
+        })
+        .await;
+}
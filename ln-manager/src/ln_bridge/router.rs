@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, StreamExt};
+use futures_timer::Interval;
+use secp256k1::key::PublicKey;
+
+use lightning::chain::chaininterface::ChainWatchInterface;
+use lightning::ln::router::{Router, RouterReadArgs};
+use lightning::util::logger::Logger;
+use lightning::util::ser::{ReadableArgs, Writeable};
+
+use super::Restorable;
+
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+fn router_file(data_path: &str) -> String {
+    format!("{}/router_data", data_path)
+}
+
+pub struct RestoreArgs {
+    data_path: String,
+    our_node_id: PublicKey,
+    chain_watcher: Arc<dyn ChainWatchInterface>,
+    logger: Arc<dyn Logger>,
+}
+
+impl RestoreArgs {
+    pub fn new(
+        data_path: String,
+        our_node_id: PublicKey,
+        chain_watcher: Arc<dyn ChainWatchInterface>,
+        logger: Arc<dyn Logger>,
+    ) -> Self {
+        RestoreArgs { data_path, our_node_id, chain_watcher, logger }
+    }
+}
+
+impl Restorable<RestoreArgs, Arc<Router>> for Router {
+    fn try_restore(args: RestoreArgs) -> Arc<Router> {
+        if let Ok(mut f) = fs::File::open(router_file(&args.data_path)) {
+            // unlike the manager the graph can be relearned from gossip, don't refuse to start
+            match Router::read(&mut f, RouterReadArgs {
+                chain_monitor: args.chain_watcher.clone(),
+                logger: args.logger.clone(),
+            }) {
+                Ok(router) => {
+                    info!("Loaded network graph from {}", router_file(&args.data_path));
+                    return Arc::new(router);
+                }
+                Err(e) => error!("Failed to deserialize network graph, starting empty: {:?}", e),
+            }
+        }
+        Arc::new(Router::new(args.our_node_id, args.chain_watcher, args.logger))
+    }
+}
+
+/// Writes the network graph to lndata, replacing the previous copy atomically
+pub fn persist(router: &Router, data_path: &str) -> io::Result<()> {
+    let filename = router_file(data_path);
+    let tmp_filename = filename.clone() + ".tmp";
+    {
+        let mut f = fs::File::create(&tmp_filename)?;
+        router.write(&mut f)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp_filename, &filename)
+}
+
+pub async fn spawn_router_persister(router: Arc<Router>, data_path: String) {
+    Interval::new(PERSIST_INTERVAL)
+        .for_each(|_| {
+            if let Err(e) = persist(&router, &data_path) {
+                error!("Failed to persist network graph: {}", e);
+            }
+            future::ready(())
+        })
+        .await;
+}
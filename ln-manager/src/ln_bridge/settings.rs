@@ -4,6 +4,46 @@ use config::{Config, ConfigError, File}; // clap-rs
 pub struct Lightning {
     pub port: u16,
     pub lndata: String,
+    // addresses to accept connections on, 0.0.0.0:{port} if empty
+    #[serde(default)]
+    pub listen: Vec<String>,
+    // SOCKS5 proxy all outbound connections go through, e.g. Tor at 127.0.0.1:9050
+    pub proxy: Option<String>,
+    // onion service pointing at one of the listen addresses, announced to the network
+    pub onion_address: Option<String>,
+}
+
+impl Lightning {
+    pub fn listen_addresses(&self) -> Vec<String> {
+        if self.listen.is_empty() {
+            vec![format!("0.0.0.0:{}", self.port)]
+        } else {
+            self.listen.clone()
+        }
+    }
+}
+
+// what we tell the network about ourselves in node_announcement
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Node {
+    // up to 32 bytes
+    pub alias: String,
+    // "#rrggbb"
+    pub color: String,
+    // public ip:port or onion addresses
+    pub addresses: Vec<String>,
+}
+
+// node events POSTed as JSON to each url (http only), see ln_bridge::webhooks
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Webhooks {
+    pub urls: Vec<String>,
+    // HMAC-SHA256 key the deliveries are signed with
+    pub secret: String,
+    // NodeEvent names to deliver, e.g. ["PaymentReceived", "ChannelClosed"], all if empty
+    pub events: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -14,7 +54,11 @@ pub struct Bitcoind {
 #[derive(Deserialize, Debug, Clone)]
 pub struct Settings {
     pub lightning: Lightning,
-    pub bitcoind: Bitcoind
+    pub bitcoind: Bitcoind,
+    #[serde(default)]
+    pub node: Node,
+    #[serde(default)]
+    pub webhooks: Webhooks,
 }
 
 impl Settings {
@@ -0,0 +1,162 @@
+use std::net::{IpAddr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::TcpStream;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+fn io_err(e: std::io::Error) -> String {
+    format!("SOCKS5 proxy: {}", e)
+}
+
+/// CONNECT request for host:port, hostnames are passed on unresolved so the proxy does the
+/// lookup (required for .onion)
+pub fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, String> {
+    let mut req = vec![VERSION, CMD_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            req.push(ATYP_IPV4);
+            req.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            req.push(ATYP_IPV6);
+            req.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.is_empty() || host.len() > 255 {
+                return Err(format!("Invalid host for SOCKS5: {}", host));
+            }
+            req.push(ATYP_DOMAIN);
+            req.push(host.len() as u8);
+            req.extend_from_slice(host.as_bytes());
+        }
+    }
+    req.push((port >> 8) as u8);
+    req.push(port as u8);
+    Ok(req)
+}
+
+fn reply_error(code: u8) -> String {
+    let reason = match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    };
+    format!("SOCKS5 proxy: {}", reason)
+}
+
+/// Runs the client side of the handshake (no authentication) and the CONNECT
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, host: &str, port: u16) -> Result<(), String> {
+    let req = connect_request(host, port)?;
+    stream.write_all(&[VERSION, 1, NO_AUTH]).await.map_err(io_err)?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.map_err(io_err)?;
+    if method[0] != VERSION || method[1] != NO_AUTH {
+        return Err("SOCKS5 proxy requires authentication".to_string());
+    }
+
+    stream.write_all(&req).await.map_err(io_err)?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await.map_err(io_err)?;
+    if reply[0] != VERSION {
+        return Err("Not a SOCKS5 proxy".to_string());
+    }
+    if reply[1] != 0 {
+        return Err(reply_error(reply[1]));
+    }
+    // skip the bound address and port
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await.map_err(io_err)?;
+            len[0] as usize
+        }
+        _ => return Err(reply_error(8)),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await.map_err(io_err)?;
+    Ok(())
+}
+
+/// Opens a connection to host:port through the proxy
+pub async fn connect(proxy: SocketAddr, host: String, port: u16) -> Result<TcpStream, String> {
+    let mut stream = TcpStream::connect(proxy).await
+        .map_err(|e| format!("Failed to connect to SOCKS5 proxy {}: {}", proxy, e))?;
+    handshake(&mut stream, &host, port).await?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // accepts one client, checks the CONNECT request and answers with `reply_code`
+    fn socks5_stub(expected_request: Vec<u8>, reply_code: u8) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            sock.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            sock.write_all(&[5, 0]).unwrap();
+
+            let mut request = vec![0u8; expected_request.len()];
+            sock.read_exact(&mut request).unwrap();
+            assert_eq!(request, expected_request);
+            sock.write_all(&[5, reply_code, 0, 1, 127, 0, 0, 1, 0x26, 0x07]).unwrap();
+            sock.write_all(b"hello").unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn connect_request_encoding() {
+        assert_eq!(connect_request("127.0.0.1", 9735).unwrap(), vec![5, 1, 0, 1, 127, 0, 0, 1, 0x26, 0x07]);
+        let v6 = connect_request("::1", 9735).unwrap();
+        assert_eq!(v6[3], ATYP_IPV6);
+        assert_eq!(v6.len(), 4 + 16 + 2);
+        let onion = connect_request("expyuzz4wqqyqhjn.onion", 9735).unwrap();
+        assert_eq!(&onion[3..5], &[ATYP_DOMAIN, 22]);
+        assert_eq!(&onion[5..27], b"expyuzz4wqqyqhjn.onion");
+    }
+
+    #[test]
+    fn connect_through_stub() {
+        let proxy = socks5_stub(connect_request("expyuzz4wqqyqhjn.onion", 9735).unwrap(), 0);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut stream = connect(proxy, "expyuzz4wqqyqhjn.onion".to_string(), 9735).await.unwrap();
+            // the proxied stream starts right after the reply
+            let mut data = [0u8; 5];
+            stream.read_exact(&mut data).await.unwrap();
+            assert_eq!(&data, b"hello");
+        });
+    }
+
+    #[test]
+    fn connect_refused_by_proxy() {
+        let proxy = socks5_stub(connect_request("127.0.0.1", 9735).unwrap(), 5);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let err = connect(proxy, "127.0.0.1".to_string(), 9735).await.err().unwrap();
+            assert_eq!(err, "SOCKS5 proxy: connection refused");
+        });
+    }
+}
@@ -0,0 +1,37 @@
+use std::fs;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Small json documents kept in lndata next to manager_data (peers, labels, history ...)
+
+pub fn read_json<T: DeserializeOwned + Default>(filename: &str) -> T {
+    match fs::read(filename) {
+        Ok(contents) => match serde_json::from_slice(&contents) {
+            Ok(value) => value,
+            Err(e) => {
+                // keep the unparsable file around, the next write would overwrite it
+                let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                let corrupt_filename = format!("{}.corrupt-{}", filename, secs);
+                error!("Failed to parse {}: {}, moving it to {}", filename, e, &corrupt_filename);
+                if let Err(e) = fs::rename(filename, &corrupt_filename) {
+                    error!("Failed to move {} aside: {}", filename, e);
+                }
+                T::default()
+            }
+        },
+        Err(_) => T::default(),
+    }
+}
+
+pub fn write_json<T: Serialize>(filename: &str, value: &T) -> std::io::Result<()> {
+    let tmp_filename = filename.to_string() + ".tmp";
+    {
+        let mut f = fs::File::create(&tmp_filename)?;
+        f.write_all(&serde_json::to_vec_pretty(value)?)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp_filename, filename)
+}
@@ -0,0 +1,347 @@
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bitcoin_hashes::hmac::{Hmac, HmacEngine};
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::{stream, StreamExt};
+use futures_timer::{Delay, Interval};
+use serde_json::json;
+
+use protocol::NodeEvent;
+
+use super::settings::Webhooks as WebhookSettings;
+use super::storage;
+use super::utils::hex_str;
+
+// "sha256=<hex HMAC-SHA256 of the body>"
+pub const SIGNATURE_HEADER: &str = "X-Rustbolt-Signature";
+// same id on every retry of a delivery, receivers can dedup on it
+pub const DELIVERY_HEADER: &str = "X-Rustbolt-Delivery";
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const BASE_BACKOFF_SECS: u64 = 10;
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+// about a day of retries
+const MAX_ATTEMPTS: u32 = 32;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut engine = HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(body);
+    hex_str(&Hmac::<sha256::Hash>::from_engine(engine).into_inner())
+}
+
+// the client has no TLS connector, https urls would fail on every attempt
+fn validate_url(url: &str) -> Result<(), String> {
+    let uri: hyper::Uri = url.parse().map_err(|e| format!("Invalid webhook url {}: {}", url, e))?;
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http"), Some(_)) => Ok(()),
+        (Some("https"), _) => Err(format!("Webhook url {} uses https, only http is supported", url)),
+        _ => Err(format!("Invalid webhook url {}, expected http://host[:port]/path", url)),
+    }
+}
+
+// wait before the next attempt after `attempts` failed ones
+fn backoff_secs(attempts: u32) -> u64 {
+    cmp::min(BASE_BACKOFF_SECS << cmp::min(attempts.saturating_sub(1), 16), MAX_BACKOFF_SECS)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Delivery {
+    id: u64,
+    url: String,
+    body: String,
+    attempts: u32,
+    // unix timestamp
+    next_attempt: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Outbox {
+    next_id: u64,
+    pending: Vec<Delivery>,
+}
+
+/// POSTs node events as JSON to the configured urls. Deliveries go through an outbox persisted
+/// in lndata so they survive restarts, failed ones are retried with exponential backoff.
+pub struct Webhooks {
+    filename: String,
+    settings: WebhookSettings,
+    outbox: Mutex<Outbox>,
+    client: hyper::Client<hyper::client::HttpConnector, hyper::Body>,
+}
+
+impl Webhooks {
+    pub fn new(data_path: &str, settings: WebhookSettings) -> Result<Self, String> {
+        if settings.secret.is_empty() {
+            return Err("webhooks need a secret to sign deliveries with".to_string());
+        }
+        for url in settings.urls.iter() {
+            validate_url(url)?;
+        }
+        let filename = format!("{}/webhook_outbox", data_path);
+        let outbox = storage::read_json(&filename);
+        Ok(Self {
+            filename,
+            settings,
+            outbox: Mutex::new(outbox),
+            client: hyper::Client::new(),
+        })
+    }
+
+    /// Queues a delivery of the event to every url
+    pub fn enqueue(&self, event: &NodeEvent) {
+        if !self.settings.events.is_empty() && !self.settings.events.iter().any(|name| name == event.name()) {
+            return;
+        }
+        let timestamp = now();
+        let mut outbox = self.outbox.lock().unwrap();
+        for url in self.settings.urls.iter() {
+            let id = outbox.next_id;
+            outbox.next_id += 1;
+            let body = json!({
+                "id": id,
+                "timestamp": timestamp,
+                "event": event,
+            });
+            outbox.pending.push(Delivery {
+                id,
+                url: url.clone(),
+                body: body.to_string(),
+                attempts: 0,
+                next_attempt: timestamp,
+            });
+        }
+        self.persist(&outbox);
+    }
+
+    /// Attempts the deliveries that are due
+    pub async fn flush(&self) {
+        let due: Vec<Delivery> = {
+            let now = now();
+            self.outbox.lock().unwrap().pending.iter().filter(|d| d.next_attempt <= now).cloned().collect()
+        };
+        if due.is_empty() {
+            return;
+        }
+        let mut results = Vec::with_capacity(due.len());
+        for delivery in due.iter() {
+            results.push((delivery.id, deliver(&self.client, &self.settings.secret, delivery).await));
+        }
+
+        let mut outbox = self.outbox.lock().unwrap();
+        for (id, result) in results {
+            let index = match outbox.pending.iter().position(|d| d.id == id) {
+                Some(index) => index,
+                None => continue,
+            };
+            match result {
+                Ok(()) => {
+                    outbox.pending.remove(index);
+                }
+                Err(e) => {
+                    let delivery = &mut outbox.pending[index];
+                    delivery.attempts += 1;
+                    if delivery.attempts >= MAX_ATTEMPTS {
+                        error!("Giving up on webhook delivery {} to {} after {} attempts: {}", id, &delivery.url, delivery.attempts, e);
+                        outbox.pending.remove(index);
+                    } else {
+                        delivery.next_attempt = now() + backoff_secs(delivery.attempts);
+                        warn!("Webhook delivery {} to {} failed, retrying in {}s: {}", id, &delivery.url, backoff_secs(delivery.attempts), e);
+                    }
+                }
+            }
+        }
+        self.persist(&outbox);
+    }
+
+    fn persist(&self, outbox: &Outbox) {
+        if let Err(e) = storage::write_json(&self.filename, outbox) {
+            error!("Failed to write webhook outbox: {}", e);
+        }
+    }
+}
+
+async fn deliver(
+    client: &hyper::Client<hyper::client::HttpConnector, hyper::Body>,
+    secret: &str,
+    delivery: &Delivery,
+) -> Result<(), String> {
+    let mut request = hyper::Request::post(&delivery.url);
+    request.header("Content-Type", "application/json");
+    request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, delivery.body.as_bytes())).as_str());
+    request.header(DELIVERY_HEADER, delivery.id.to_string().as_str());
+    let request = request.body(hyper::Body::from(delivery.body.clone())).map_err(|e| e.to_string())?;
+    let response = Box::pin(client.request(request));
+    match future::select(response, Box::pin(Delay::new(DELIVERY_TIMEOUT))).await {
+        Either::Left((Ok(response), _)) => {
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("status {}", response.status()))
+            }
+        }
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
+
+/// Queues every event from the bus and works through the outbox until the node stops
+pub async fn spawn_webhook_dispatcher(webhooks: Arc<Webhooks>, events: mpsc::Receiver<NodeEvent>) {
+    // queueing never waits on deliveries, the bus drops subscribers that fall behind
+    let (mut wake, woken) = mpsc::channel(1);
+    let queue = events.for_each(|event| {
+        webhooks.enqueue(&event);
+        let _ = wake.try_send(());
+        future::ready(())
+    });
+    let deliveries = stream::select(woken, Interval::new(RETRY_INTERVAL)).for_each(|_| webhooks.flush());
+    future::join(queue, deliveries).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc as std_mpsc;
+    use std::thread;
+
+    fn data_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rustbolt-webhooks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn settings(url: &str, events: Vec<&str>) -> WebhookSettings {
+        WebhookSettings {
+            urls: vec![url.to_string()],
+            secret: "secret".to_string(),
+            events: events.into_iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn payment_received() -> NodeEvent {
+        NodeEvent::PaymentReceived { payment_hash: "00".repeat(32), amount_msat: 1000 }
+    }
+
+    // answers one request with `status` and hands back (headers, body)
+    fn http_stub(status: &'static str) -> (String, std_mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = std_mpsc::channel();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let (headers, body) = loop {
+                let n = sock.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if n == 0 && !text.contains("\r\n\r\n") {
+                    break (String::new(), String::new());
+                }
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let headers = text[..end].to_lowercase();
+                    let len: usize = headers.lines()
+                        .find(|line| line.starts_with("content-length:"))
+                        .map(|line| line["content-length:".len()..].trim().parse().unwrap())
+                        .unwrap_or(0);
+                    if text.len() >= end + 4 + len || n == 0 {
+                        break (headers, text[end + 4..].to_string());
+                    }
+                }
+            };
+            sock.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes()).unwrap();
+            tx.send((headers, body)).unwrap();
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_an_hour() {
+        assert_eq!(backoff_secs(1), 10);
+        assert_eq!(backoff_secs(2), 20);
+        assert_eq!(backoff_secs(5), 160);
+        assert_eq!(backoff_secs(MAX_ATTEMPTS), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn delivers_signed_event() {
+        let (url, requests) = http_stub("200 OK");
+        let dir = data_dir("deliver");
+        let webhooks = Webhooks::new(&dir, settings(&url, vec![])).unwrap();
+        webhooks.enqueue(&payment_received());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(webhooks.flush());
+
+        let (headers, body) = requests.recv().unwrap();
+        assert!(headers.starts_with("post /hook "));
+        let signature = format!("{}: sha256={}", SIGNATURE_HEADER.to_lowercase(), sign("secret", body.as_bytes()));
+        assert!(headers.lines().any(|line| line == signature));
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["event"]["PaymentReceived"]["amount_msat"], 1000);
+        assert!(webhooks.outbox.lock().unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn failed_delivery_stays_in_outbox() {
+        let (url, requests) = http_stub("500 Internal Server Error");
+        let dir = data_dir("retry");
+        let webhooks = Webhooks::new(&dir, settings(&url, vec![])).unwrap();
+        webhooks.enqueue(&payment_received());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(webhooks.flush());
+        requests.recv().unwrap();
+
+        // survives a restart, scheduled for later
+        let reloaded = Webhooks::new(&dir, settings(&url, vec![])).unwrap();
+        let outbox = reloaded.outbox.lock().unwrap();
+        assert_eq!(outbox.pending.len(), 1);
+        assert_eq!(outbox.pending[0].attempts, 1);
+        assert!(outbox.pending[0].next_attempt > now());
+    }
+
+    #[test]
+    fn rejects_non_http_urls() {
+        let dir = data_dir("urls");
+        assert!(Webhooks::new(&dir, settings("https://127.0.0.1/hook", vec![])).is_err());
+        assert!(Webhooks::new(&dir, settings("127.0.0.1:9000/hook", vec![])).is_err());
+        assert!(Webhooks::new(&dir, settings("http://127.0.0.1:9000/hook", vec![])).is_ok());
+    }
+
+    #[test]
+    fn corrupt_outbox_is_moved_aside() {
+        let dir = data_dir("corrupt");
+        let filename = format!("{}/webhook_outbox", dir);
+        fs::write(&filename, "{\"next_id\": 3, \"pend").unwrap();
+        let webhooks = Webhooks::new(&dir, settings("http://127.0.0.1:1/hook", vec![])).unwrap();
+        assert!(webhooks.outbox.lock().unwrap().pending.is_empty());
+
+        // the next write doesn't lose what was there
+        webhooks.enqueue(&payment_received());
+        let moved: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("webhook_outbox.corrupt-"))
+            .collect();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(fs::read_to_string(format!("{}/{}", dir, moved[0])).unwrap(), "{\"next_id\": 3, \"pend");
+    }
+
+    #[test]
+    fn filters_events() {
+        let dir = data_dir("filter");
+        let webhooks = Webhooks::new(&dir, settings("http://127.0.0.1:1/hook", vec!["ChannelClosed"])).unwrap();
+        webhooks.enqueue(&payment_received());
+        assert!(webhooks.outbox.lock().unwrap().pending.is_empty());
+    }
+}
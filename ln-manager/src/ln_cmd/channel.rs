@@ -1,35 +1,69 @@
 use futures::channel::mpsc;
+use std::collections::HashSet;
 use std::sync::Arc;
 
-use lightning::ln::channelmanager::ChannelManager;
+use rand::Rng;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use bitcoin_hashes::sha256d::Hash as Sha256dHash;
+use lightning::chain::transaction::OutPoint;
+use lightning::ln::channelmanager::{ChannelDetails, ChannelManager};
+use lightning::ln::msgs::RoutingMessageHandler;
+use lightning::ln::router;
+use secp256k1::key::PublicKey;
+use protocol::{ChannelInfo, ChannelState, CloseType, ClosedChannelInfo};
+use crate::executor::Larva;
+use crate::ln_bridge::chain_monitor::ChainBroadcaster;
+use crate::ln_bridge::channel_monitor::ChannelMonitor;
+use crate::ln_bridge::closed_channels::ClosedChannels;
+use crate::ln_bridge::close_addresses::CloseAddresses;
+use crate::ln_bridge::channel_labels::ChannelLabels;
+use crate::ln_bridge::channel_manager::user_config;
 use crate::ln_bridge::utils::{hex_str, hex_to_vec, hex_to_compressed_pubkey};
-use serde_json::json;
 
 pub trait ChannelC {
-    fn fund_channel(&self, line: Vec<String>) -> Result<String, String>;
-    fn close(&self, line: String) -> Result<String, String>;
+    fn fund_channel(&self, node_id: String, value_sat: u64, push_msat: u64, private: bool, close_to: Option<String>) -> Result<ChannelInfo, String>;
+    fn close(&self, line: String, close_to: Option<String>) -> Result<String, String>;
+    fn force_close(&self, line: String) -> Result<(String, Option<String>), String>;
     fn force_close_all(&self);
-    fn channel_list(&self, mode: &str) -> Vec<String>;
+    fn channel_list(&self, state: Option<ChannelState>, peer: Option<String>) -> Result<Vec<ChannelInfo>, String>;
+    fn closed_channel_list(&self) -> Vec<ClosedChannelInfo>;
+    fn label(&self, channel: String, label: String) -> Result<(String, String), String>;
 }
 
-// fund channel
+// fund channel, optionally unannounced and with the address a cooperative close should pay to
 pub fn fund_channel (
-    args: Vec<String>,
+    node_id: String,
+    value_sat: u64,
+    push_msat: u64,
+    private: bool,
+    close_to: Option<String>,
     channel_manager: &Arc<ChannelManager>,
+    monitor: &Arc<ChannelMonitor>,
+    router: &Arc<router::Router>,
+    labels: &Arc<ChannelLabels>,
+    close_addresses: &Arc<CloseAddresses>,
     mut event_notify: mpsc::Sender<()>,
-) -> Result<String, String> {
-    let pubkey_str = &args[0];
-    let value_str = &args[1];
-    let push_str = &args[2];
-    match hex_to_compressed_pubkey(&pubkey_str) {
+) -> Result<ChannelInfo, String> {
+    if let Some(ref address) = close_to {
+        close_addresses.validate(address)?;
+    }
+    match hex_to_compressed_pubkey(&node_id) {
         Some(pubkey) => {
-            let value = value_str.parse().unwrap_or(100000);
-            let push = push_str.parse().unwrap_or(500000);
-            match channel_manager.create_channel(pubkey, value, push, 0) {
+            // the channel id isn't known yet, user_id ties the close address to the channel
+            let user_id = rand::thread_rng().gen::<u64>();
+            let mut config = user_config();
+            config.channel_options.announced_channel = !private;
+            match channel_manager.create_channel(pubkey, value_sat, push_msat, user_id, Some(config)) {
                 Ok(_) => { 
-                    info!("Channel created, {} sending open_channel ...", pubkey_str); 
+                    info!("Channel created, {} sending open_channel ...", node_id); 
+                    if let Some(address) = close_to {
+                        close_addresses.set_for_user_id(user_id, address);
+                    }
                     let _ = event_notify.try_send(());
-                    Ok(String::from(pubkey_str))
+                    channel_manager.list_channels().into_iter()
+                        .find(|channel| channel.user_id == user_id)
+                        .map(|channel| channel_info(channel, false, monitor, router, labels))
+                        .ok_or(format!("Channel with {} vanished before it was opened", node_id))
                 }
                 Err(e) => { 
                     let err_str = format!("Failed to open channel: {:?}!", e);
@@ -47,60 +81,239 @@ pub fn fund_channel (
     }
 }
 
-// Close single channel
-pub fn close(
-    ch_id: String,
+// Resolves a channel given as channel id, short channel id (BLOCKxTXxOUT),
+// funding outpoint (txid:vout) or label
+fn resolve_channel_id(
+    channel: &str,
     channel_manager: &Arc<ChannelManager>,
-    mut event_notify: mpsc::Sender<()>,
-) -> Result<String, String> {
-    if ch_id.len() == 64 {
-        if let Some(chan_id_vec) = hex_to_vec(&ch_id) {
+    labels: &Arc<ChannelLabels>,
+) -> Result<[u8; 32], String> {
+    if channel.len() == 64 {
+        if let Some(chan_id_vec) = hex_to_vec(channel) {
             let mut channel_id = [0; 32];
             channel_id.copy_from_slice(&chan_id_vec);
-            debug!("called close");
-            match channel_manager.close_channel(&channel_id) {
-                Ok(()) => {
-                    let _ = event_notify.try_send(());
-                    info!("Channel closing: {}", &ch_id);
-                    Ok(ch_id.to_string())
-                }
-                Err(e) => { 
-                    debug!("Failed to close channel: {:?}", e);
-                    Err(format!("Channel Close Failure: {:?}", e).to_string())
-                }
+            return Ok(channel_id);
+        }
+    }
+    if let Some(short_channel_id) = protocol::parse_short_channel_id(channel) {
+        return channel_manager.list_channels().into_iter()
+            .find(|c| c.short_channel_id == Some(short_channel_id))
+            .map(|c| c.channel_id)
+            .ok_or(format!("No channel with short channel id {}", channel));
+    }
+    let outpoint: Vec<&str> = channel.split(':').collect();
+    if outpoint.len() == 2 {
+        let txid = Sha256dHash::from_hex(outpoint[0]).map_err(|_| format!("Invalid funding txid: {}", outpoint[0]))?;
+        let index: u16 = outpoint[1].parse().map_err(|_| format!("Invalid funding output: {}", outpoint[1]))?;
+        return Ok(OutPoint { txid, index }.to_channel_id());
+    }
+    match labels.find(channel).and_then(|id| hex_to_vec(&id)) {
+        Some(chan_id_vec) => {
+            let mut channel_id = [0; 32];
+            channel_id.copy_from_slice(&chan_id_vec);
+            Ok(channel_id)
+        }
+        None => {
+            debug!("Unknown channel {} ...", channel);
+            Err(format!("Unknown channel: {}", channel))
+        }
+    }
+}
+
+// Resolves a peer given as node id or node alias from gossip
+fn resolve_peer(peer: &str, router: &Arc<router::Router>) -> Result<PublicKey, String> {
+    if peer.len() == 33 * 2 {
+        if let Some(pubkey) = hex_to_compressed_pubkey(peer) {
+            return Ok(pubkey);
+        }
+    }
+    let mut starting_point = None;
+    loop {
+        let batch = router.get_next_node_announcements(starting_point.as_ref(), 255);
+        if batch.is_empty() {
+            return Err(format!("Unknown peer: {}", peer));
+        }
+        for announcement in batch.iter() {
+            let alias = &announcement.contents.alias;
+            let len = alias.iter().position(|b| *b == 0).unwrap_or(alias.len());
+            if String::from_utf8_lossy(&alias[..len]) == peer {
+                return Ok(announcement.contents.node_id);
             }
-        } else {
-            debug!("Invalid channel_id ...");
-            Err(format!("Invalid channel_id"))
         }
-    } else {
-        debug!("Channel id has invalid length ...");
-        Err(format!("Channel id has invalid length ..."))
+        starting_point = batch.last().map(|announcement| announcement.contents.node_id);
+    }
+}
+
+// Label a channel
+pub fn label(
+    channel: String,
+    label: String,
+    channel_manager: &Arc<ChannelManager>,
+    labels: &Arc<ChannelLabels>,
+) -> Result<(String, String), String> {
+    let is_reserved = label.len() == 64
+        || label.contains(':')
+        || protocol::parse_short_channel_id(&label).is_some();
+    if label.is_empty() || is_reserved {
+        return Err(format!("Invalid label: {}", label));
+    }
+    let channel_id = resolve_channel_id(&channel, channel_manager, labels)?;
+    if !channel_manager.list_channels().iter().any(|c| c.channel_id == channel_id) {
+        return Err(format!("Unknown channel: {}", &channel));
+    }
+    labels.set(&channel_id, label.clone())?;
+    Ok((hex_str(&channel_id), label))
+}
+
+// Close single channel, the funds end up at close_to (or the one given at creation) if set
+pub fn close<T: Larva>(
+    ch_id: String,
+    close_to: Option<String>,
+    channel_manager: &Arc<ChannelManager>,
+    closed_channels: &Arc<ClosedChannels<T>>,
+    close_addresses: &Arc<CloseAddresses>,
+    labels: &Arc<ChannelLabels>,
+    mut event_notify: mpsc::Sender<()>,
+) -> Result<String, String> {
+    let channel_id = resolve_channel_id(&ch_id, channel_manager, labels)?;
+    debug!("called close");
+    if let Some(address) = close_to {
+        close_addresses.validate(&address)?;
+        close_addresses.set_for_channel(&channel_id, address);
+    }
+    match channel_manager.close_channel(&channel_id) {
+        Ok(()) => {
+            closed_channels.note_local_close(channel_id, CloseType::Cooperative);
+            let _ = event_notify.try_send(());
+            info!("Channel closing: {}", &ch_id);
+            Ok(hex_str(&channel_id))
+        }
+        Err(e) => { 
+            debug!("Failed to close channel: {:?}", e);
+            Err(format!("Channel Close Failure: {:?}", e).to_string())
+        }
     }
 }
 
+// Force close single channel, returns the id and the commitment txid we broadcast
+pub fn force_close<T: Larva>(
+    ch_id: String,
+    channel_manager: &Arc<ChannelManager>,
+    monitor: &Arc<ChannelMonitor>,
+    closed_channels: &Arc<ClosedChannels<T>>,
+    broadcaster: &Arc<ChainBroadcaster<T>>,
+    labels: &Arc<ChannelLabels>,
+    mut event_notify: mpsc::Sender<()>,
+) -> Result<(String, Option<String>), String> {
+    let channel_id = resolve_channel_id(&ch_id, channel_manager, labels)?;
+    if !channel_manager.list_channels().iter().any(|channel| channel.channel_id == channel_id) {
+        return Err(format!("Unknown channel: {}", &ch_id));
+    }
+    closed_channels.note_local_close(channel_id, CloseType::LocalForce);
+    // broadcasts the latest local commitment through the ChainBroadcaster right away
+    channel_manager.force_close_channel(&channel_id);
+    let _ = event_notify.try_send(());
+    info!("Channel force closed: {}", &ch_id);
+
+    let commitment_txid = monitor.get_funding_txo(&channel_id).and_then(|funding_txo| {
+        broadcaster.get_spending_txn(&funding_txo.txid).into_iter().find(|tx| {
+            tx.input.iter().any(|input| input.previous_output.vout == funding_txo.index as u32)
+        })
+    }).map(|tx| tx.txid().to_hex());
+    if commitment_txid.is_none() {
+        // never funded, nothing to broadcast
+        debug!("No commitment transaction broadcast for {}", &ch_id);
+    }
+    Ok((hex_str(&channel_id), commitment_txid))
+}
+
 // Force close all channels
-pub fn force_close_all(channel_manager: &Arc<ChannelManager>) {
+pub fn force_close_all<T: Larva>(channel_manager: &Arc<ChannelManager>, closed_channels: &Arc<ClosedChannels<T>>) {
+    for channel in channel_manager.list_channels() {
+        closed_channels.note_local_close(channel.channel_id, CloseType::LocalForce);
+    }
     channel_manager.force_close_all_channels();
 }
 
-// List existing channels
-pub fn channel_list( channel_manager: &Arc<ChannelManager>, mode: &str) -> Vec<String> {
-    let channels = match mode {
-        "live" => { channel_manager.list_usable_channels() }
-        "all" | _ => { channel_manager.list_channels() }
+// A channel is public once its announcement made it into our routing table
+fn is_announced(router: &Arc<router::Router>, short_channel_id: u64) -> bool {
+    router
+        .get_next_channel_announcements(short_channel_id, 1)
+        .first()
+        .map(|(announcement, _, _)| announcement.contents.short_channel_id == short_channel_id)
+        .unwrap_or(false)
+}
+
+// List existing channels, optionally filtered by state and peer (node id or alias)
+pub fn channel_list(
+    channel_manager: &Arc<ChannelManager>,
+    monitor: &Arc<ChannelMonitor>,
+    router: &Arc<router::Router>,
+    labels: &Arc<ChannelLabels>,
+    state_filter: Option<ChannelState>,
+    peer: Option<String>,
+) -> Result<Vec<ChannelInfo>, String> {
+    let peer_filter = match peer {
+        Some(peer) => Some(resolve_peer(&peer, router)?),
+        None => None,
+    };
+
+    let usable: HashSet<[u8; 32]> = channel_manager
+        .list_usable_channels()
+        .into_iter()
+        .map(|channel| channel.channel_id)
+        .collect();
+
+    Ok(channel_manager.list_channels().into_iter().filter(|channel| {
+        peer_filter.map_or(true, |pubkey| channel.remote_network_id == pubkey)
+    }).map(|channel| {
+        let is_usable = usable.contains(&channel.channel_id);
+        channel_info(channel, is_usable, monitor, router, labels)
+    }).filter(|info| {
+        state_filter.as_ref().map_or(true, |state| &info.state == state)
+    }).collect())
+}
+
+fn channel_info(
+    channel: ChannelDetails,
+    is_usable: bool,
+    monitor: &Arc<ChannelMonitor>,
+    router: &Arc<router::Router>,
+    labels: &Arc<ChannelLabels>,
+) -> ChannelInfo {
+    let state = match channel.short_channel_id {
+        None => ChannelState::Pending,
+        Some(_) if is_usable => ChannelState::Active,
+        Some(_) => ChannelState::Inactive,
     };
-    channels.into_iter().map(|channel| {
-        let (id, confirmed) = match channel.short_channel_id {
-            Some(short_id) => { (format!("{}",short_id), true) }
-            None => { ("".to_string(), false) }
-        };
-        json!({ 
-            "id": hex_str(&channel.channel_id[..]), 
-            "confirmed": confirmed,
-            "short_id": id,
-            "peer": hex_str(&channel.remote_network_id.serialize()),
-            "value_sats": channel.channel_value_satoshis
-        }).to_string()
-    }).collect()
+    let is_public = channel.short_channel_id
+        .map(|short_id| is_announced(router, short_id))
+        .unwrap_or(false);
+    let funding_txo = monitor.get_funding_txo(&channel.channel_id).map(|txo| {
+        format!("{}:{}", txo.txid.to_hex(), txo.index)
+    });
+    // ChannelDetails doesn't break this down into reserves, commitment fee and HTLCs
+    let unavailable_msat = (channel.channel_value_satoshis * 1000)
+        .saturating_sub(channel.outbound_capacity_msat)
+        .saturating_sub(channel.inbound_capacity_msat);
+    ChannelInfo {
+        id: hex_str(&channel.channel_id[..]),
+        label: labels.get(&channel.channel_id),
+        short_channel_id: channel.short_channel_id,
+        peer: hex_str(&channel.remote_network_id.serialize()),
+        user_id: channel.user_id,
+        state,
+        is_usable,
+        is_public,
+        funding_txo,
+        value_sats: channel.channel_value_satoshis,
+        outbound_capacity_msat: channel.outbound_capacity_msat,
+        inbound_capacity_msat: channel.inbound_capacity_msat,
+        unavailable_msat,
+    }
+}
+
+// History of closed channels
+pub fn closed_channel_list<T: Larva>(closed_channels: &Arc<ClosedChannels<T>>) -> Vec<ClosedChannelInfo> {
+    closed_channels.list()
 }
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use lightning::ln::msgs::{ChannelAnnouncement, ChannelUpdate, NodeAnnouncement, RoutingMessageHandler};
+use lightning::ln::router;
+use protocol::{GraphChannelInfo, GraphNodeInfo, RoutingPolicy};
+use crate::ln_bridge::net_address::format_net_address;
+use crate::ln_bridge::utils::hex_str;
+
+pub trait GraphC {
+    fn graph_nodes(&self) -> Vec<GraphNodeInfo>;
+    fn graph_channels(&self) -> Vec<GraphChannelInfo>;
+    fn graph_node(&self, node: String) -> Result<GraphNodeInfo, String>;
+    fn graph_channel(&self, short_channel_id: String) -> Result<GraphChannelInfo, String>;
+}
+
+fn node_info(announcement: &NodeAnnouncement) -> GraphNodeInfo {
+    let contents = &announcement.contents;
+    let len = contents.alias.iter().position(|b| *b == 0).unwrap_or(contents.alias.len());
+    GraphNodeInfo {
+        node_id: hex_str(&contents.node_id.serialize()),
+        alias: String::from_utf8_lossy(&contents.alias[..len]).to_string(),
+        color: format!("#{}", hex_str(&contents.rgb)),
+        addresses: contents.addresses.iter().map(format_net_address).collect(),
+        last_update: contents.timestamp,
+    }
+}
+
+fn policy(update: &ChannelUpdate) -> RoutingPolicy {
+    let contents = &update.contents;
+    RoutingPolicy {
+        fee_base_msat: contents.fee_base_msat,
+        fee_proportional_millionths: contents.fee_proportional_millionths,
+        cltv_expiry_delta: contents.cltv_expiry_delta,
+        htlc_minimum_msat: contents.htlc_minimum_msat,
+        disabled: contents.flags & 2 == 2,
+        last_update: contents.timestamp,
+    }
+}
+
+fn channel_info(announcement: &ChannelAnnouncement, update_a: &ChannelUpdate, update_b: &ChannelUpdate) -> GraphChannelInfo {
+    let contents = &announcement.contents;
+    // the direction bit tells which side sent the update
+    let (one_to_two, two_to_one) = if update_a.contents.flags & 1 == 0 {
+        (update_a, update_b)
+    } else {
+        (update_b, update_a)
+    };
+    GraphChannelInfo {
+        short_channel_id: contents.short_channel_id,
+        node1: hex_str(&contents.node_id_1.serialize()),
+        node2: hex_str(&contents.node_id_2.serialize()),
+        node1_policy: policy(one_to_two),
+        node2_policy: policy(two_to_one),
+    }
+}
+
+// Every node the router has a node_announcement for
+pub fn graph_nodes(router: &Arc<router::Router>) -> Vec<GraphNodeInfo> {
+    let mut nodes = Vec::new();
+    let mut starting_point = None;
+    loop {
+        let batch = router.get_next_node_announcements(starting_point.as_ref(), 255);
+        if batch.is_empty() {
+            return nodes;
+        }
+        nodes.extend(batch.iter().map(node_info));
+        starting_point = batch.last().map(|announcement| announcement.contents.node_id);
+    }
+}
+
+// Every announced channel, the router only hands out channels with updates from both sides
+pub fn graph_channels(router: &Arc<router::Router>) -> Vec<GraphChannelInfo> {
+    let mut channels = Vec::new();
+    let mut starting_point = 0;
+    loop {
+        let batch = router.get_next_channel_announcements(starting_point, 255);
+        if batch.is_empty() {
+            return channels;
+        }
+        channels.extend(batch.iter().map(|(announcement, update_a, update_b)| channel_info(announcement, update_a, update_b)));
+        // the starting point is inclusive
+        starting_point = batch.last().map_or(0, |(announcement, _, _)| announcement.contents.short_channel_id) + 1;
+    }
+}
+
+// Node given as node id or alias
+pub fn graph_node(node: String, router: &Arc<router::Router>) -> Result<GraphNodeInfo, String> {
+    graph_nodes(router)
+        .into_iter()
+        .find(|info| info.node_id == node || info.alias == node)
+        .ok_or(format!("Unknown node: {}", node))
+}
+
+// Channel given as BLOCKxTXxOUT or the numeric short channel id
+pub fn graph_channel(short_channel_id: String, router: &Arc<router::Router>) -> Result<GraphChannelInfo, String> {
+    let id = protocol::parse_short_channel_id(&short_channel_id)
+        .or_else(|| short_channel_id.parse::<u64>().ok())
+        .ok_or(format!("Invalid short channel id: {}", short_channel_id))?;
+    router.get_next_channel_announcements(id, 1)
+        .iter()
+        .find(|(announcement, _, _)| announcement.contents.short_channel_id == id)
+        .map(|(announcement, update_a, update_b)| channel_info(announcement, update_a, update_b))
+        .ok_or(format!("Unknown channel: {}", short_channel_id))
+}
@@ -6,6 +6,8 @@ use lightning::ln::channelmanager::{ChannelManager, PaymentHash, PaymentPreimage
 use lightning::ln::router;
 use lightning_invoice::Invoice;
 use lightning_invoice::MinFinalCltvExpiry;
+use protocol::{InvoiceInfo, PaymentInfo, PaymentLimits, RouteHopInfo, RouteInfo};
+use secp256k1::key::PublicKey;
 use secp256k1::{All, Secp256k1};
 use rand::{thread_rng, Rng};
 use std;
@@ -13,127 +15,179 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-use crate::ln_bridge::utils::{hex_str, slice_to_be64};
+use crate::ln_bridge::utils::{hex_str, hex_to_compressed_pubkey, slice_to_be64};
 use crate::utils::{to_network, to_currency};
 
 pub trait InvoiceC {
-    fn pay(&self, args: Vec<String>) -> Result<(), String>;
-    fn create_invoice(&self, line: String) -> Result<String, String>;
+    fn pay(&self, invoice: String, amount_msat: Option<u64>, limits: PaymentLimits) -> Result<PaymentInfo, String>;
+    fn create_invoice(&self, amount_pico_btc: u64, description: Option<String>) -> Result<InvoiceInfo, String>;
+    fn query_route(&self, target: String, amount_msat: Option<u64>, final_cltv: Option<u32>) -> Result<RouteInfo, String>;
 }
 
+// used when the invoice doesn't set min_final_cltv_expiry
+const DEFAULT_FINAL_CLTV: MinFinalCltvExpiry = MinFinalCltvExpiry(9);
+
+// What get_route needs to reach an invoice's payee
+struct RouteTarget {
+    payee: PublicKey,
+    route_hint: Vec<router::RouteHint>,
+    final_cltv: u32,
+}
+
+fn route_target(invoice: &Invoice) -> Result<RouteTarget, String> {
+    if let Some(pubkey) = invoice.payee_pub_key() {
+        if *pubkey != invoice.recover_payee_pub_key() {
+            warn!(
+                "Invoice had non-equal duplicative target node_id (ie was malformed)"
+            );
+            return Err("Invoice payee doesn't match its signature".to_string());
+        }
+    }
+
+    let mut route_hint = Vec::with_capacity(invoice.routes().len());
+    for route in invoice.routes() {
+        if route.len() != 1 {
+            debug!("Invoice contained multi-hop non-public route, ignoring as yet unsupported");
+        } else {
+            route_hint.push(router::RouteHint {
+                src_node_id: route[0].pubkey,
+                short_channel_id: slice_to_be64(&route[0].short_channel_id),
+                fee_base_msat: route[0].fee_base_msat,
+                fee_proportional_millionths: route[0].fee_proportional_millionths,
+                cltv_expiry_delta: route[0].cltv_expiry_delta,
+                htlc_minimum_msat: 0,
+            });
+        }
+    }
+    let raw_invoice = invoice.clone().into_signed_raw();
+    let final_cltv = raw_invoice.min_final_cltv_expiry().unwrap_or(&DEFAULT_FINAL_CLTV);
+    if final_cltv.0 > std::u32::MAX as u64 {
+        debug!("Invoice had garbage final cltv");
+        return Err("Invoice had garbage final cltv".to_string());
+    }
+    Ok(RouteTarget {
+        payee: invoice.recover_payee_pub_key(),
+        route_hint,
+        final_cltv: final_cltv.0 as u32,
+    })
+}
+
+// Invoice amount in msat, None for "any amount" invoices
+fn invoice_amount_msat(invoice: &Invoice) -> Option<u64> {
+    invoice.amount_pico_btc().and_then(|amt| {
+        if amt % 10 != 0 {
+            None
+        } else {
+            Some(amt / 10)
+        }
+    })
+}
+
+fn find_route(
+    target: &RouteTarget,
+    amt: u64,
+    channel_manager: &Arc<ChannelManager>,
+    router: &Arc<router::Router>,
+) -> Result<router::Route, String> {
+    info!("invoice route length: {}", target.route_hint.len());
+    let usable_channels = channel_manager.list_usable_channels();
+    info!("usable channel length: {}", usable_channels.len());
+
+    router.get_route(
+        &target.payee,
+        Some(&usable_channels),
+        &target.route_hint,
+        amt,
+        target.final_cltv,
+    ).map_err(|e| {
+        info!("Failed to find route: {}", e.err);
+        "Failed to find route".to_string()
+    })
+}
+
+// Refuses routes that would exceed the fee or timelock budget of the payment
+fn check_limits(limits: &PaymentLimits, route: &router::Route, amt: u64) -> Result<(), String> {
+    let (total_fee_msat, total_cltv) = route_totals(route);
+    if let Some(max_fee_msat) = limits.max_fee_msat {
+        if total_fee_msat > max_fee_msat {
+            return Err(format!("Route fee of {} msat exceeds max_fee_msat of {} msat", total_fee_msat, max_fee_msat));
+        }
+    }
+    if let Some(max_fee_percent) = limits.max_fee_percent {
+        if total_fee_msat as f64 > amt as f64 * max_fee_percent / 100.0 {
+            return Err(format!("Route fee of {} msat exceeds max_fee_percent of {}% of {} msat", total_fee_msat, max_fee_percent, amt));
+        }
+    }
+    if let Some(max_total_cltv) = limits.max_total_cltv {
+        if total_cltv > max_total_cltv {
+            return Err(format!("Route timelock of {} blocks exceeds max_total_cltv of {} blocks", total_cltv, max_total_cltv));
+        }
+    }
+    Ok(())
+}
+
+// Fee paid to intermediate hops and the timelock of the first HTLC, the last hop carries the
+// payment amount and the final cltv
+fn route_totals(route: &router::Route) -> (u64, u32) {
+    let total_fee_msat = route.hops[..route.hops.len().saturating_sub(1)].iter().map(|hop| hop.fee_msat).sum();
+    let total_cltv = route.hops.iter().map(|hop| hop.cltv_expiry_delta).sum();
+    (total_fee_msat, total_cltv)
+}
+
+// Pays an invoice, amount_msat is only used when the invoice has no amount
 pub fn pay(
-    args: Vec<String>,
+    invoice: String,
+    amount_msat: Option<u64>,
+    limits: PaymentLimits,
     channel_manager: &Arc<ChannelManager>,
     mut event_notify: mpsc::Sender<()>,
     network: &Network,
     router: &Arc<router::Router>,
-) -> Result<(), String> {
-    macro_rules! fail_return {
-        () => {
-            println!(">");
-            return Ok(());
-        };
+) -> Result<PaymentInfo, String> {
+    if let Some(percent) = limits.max_fee_percent {
+        if percent.is_nan() || percent < 0.0 {
+            return Err(format!("Invalid value for max_fee_percent: {}", percent));
+        }
     }
-    let invoice_str = &args[0];
-    match Invoice::from_str(invoice_str) {
+    match Invoice::from_str(&invoice) {
         Ok(invoice) => {
-            // Raw Invoice Generated Here
-            let raw_invoice = invoice.clone().into_signed_raw();
             let invoice_network = to_network(invoice.currency());
             if invoice_network != *network {
                 Err("Wrong network on invoice".to_string())
             } else {
-                let amt = if let Some(amt) = invoice.amount_pico_btc().and_then(|amt| {
-                    if amt % 10 != 0 {
-                        None
-                    } else {
-                        Some(amt / 10)
-                    }
-                }) {
-                    if args.len() == 2 {
-                        warn!("Invoice had amount, you shouldn't specify one");
-                    }
-                    amt
-                } else {
-                    if args.len() == 1 {
-                        warn!("Invoice didn't have an amount, you should specify one");
-                        fail_return!();
-                    }
-                    match args[1].parse() {
-                        Ok(amt) => amt,
-                        Err(_) => {
-                            warn!("Provided amount was garbage");
-                            fail_return!();
+                let amt = match (invoice_amount_msat(&invoice), amount_msat) {
+                    (Some(amt), given) => {
+                        if given.is_some() {
+                            warn!("Invoice had amount, you shouldn't specify one");
                         }
+                        amt
                     }
+                    (None, Some(amt)) => amt,
+                    (None, None) => return Err("Invoice has no amount, specify one".to_string()),
                 };
 
-                if let Some(pubkey) = invoice.payee_pub_key() {
-                    if *pubkey != invoice.recover_payee_pub_key() {
-                        warn!(
-                            "Invoice had non-equal duplicative target node_id (ie was malformed)"
-                        );
-                        fail_return!();
-                    }
-                }
-
-                let mut route_hint = Vec::with_capacity(invoice.routes().len());
-                for route in invoice.routes() {
-                    if route.len() != 1 {
-                        debug!("Invoice contained multi-hop non-public route, ignoring as yet unsupported");
-                    } else {
-                        route_hint.push(router::RouteHint {
-                            src_node_id: route[0].pubkey,
-                            short_channel_id: slice_to_be64(&route[0].short_channel_id),
-                            fee_base_msat: route[0].fee_base_msat,
-                            fee_proportional_millionths: route[0].fee_proportional_millionths,
-                            cltv_expiry_delta: route[0].cltv_expiry_delta,
-                            htlc_minimum_msat: 0,
-                        });
-                    }
-                }
-                let final_cltv = if invoice.min_final_cltv_expiry().is_none() {
-                    &MinFinalCltvExpiry(9)
-                } else {
-                    raw_invoice.min_final_cltv_expiry().unwrap()
+                let target = route_target(&invoice)?;
+                let route = find_route(&target, amt, channel_manager, router)?;
+                check_limits(&limits, &route, amt)?;
+                let info = PaymentInfo {
+                    payment_hash: hex_str(&invoice.payment_hash().into_inner()),
+                    destination: hex_str(&target.payee.serialize()),
+                    route: route_info(&route, amt),
                 };
-                if final_cltv.0 > std::u32::MAX as u64 {
-                    debug!("Invoice had garbage final cltv");
-                    fail_return!();
-                }
-
-                info!("invoice route length: {}", invoice.routes().len());
-                let usable_channels_len = &channel_manager.list_usable_channels().len();
-                info!("usable channel length: {}", usable_channels_len);
-
-                match router.get_route(
-                    &invoice.recover_payee_pub_key(),
-                    Some(&channel_manager.list_usable_channels()),
-                    &route_hint,
-                    amt,
-                    final_cltv.0 as u32,
-                ) {
-                    Ok(route) => {
-                        let mut payment_hash = PaymentHash([0; 32]);
-                        payment_hash
-                            .0
-                            .copy_from_slice(&invoice.payment_hash().into_inner()[..]);
-                        match channel_manager.send_payment(route, payment_hash) {
-                            Ok(()) => {
-                                info!("Sending {} msat", amt);
-                                let _ = event_notify.try_send(());
-                                Ok(())
-                            }
-                            Err(e) => {
-                                let error = format!("Failed to send HTLC: {:?}", e);
-                                debug!("{}", error);
-                                Err(error)
-                            }
-                        }
+                let mut payment_hash = PaymentHash([0; 32]);
+                payment_hash
+                    .0
+                    .copy_from_slice(&invoice.payment_hash().into_inner()[..]);
+                match channel_manager.send_payment(route, payment_hash) {
+                    Ok(()) => {
+                        info!("Sending {} msat", amt);
+                        let _ = event_notify.try_send(());
+                        Ok(info)
                     }
                     Err(e) => {
-                        info!("Failed to find route: {}", e.err);
-                        Err("Failed to find route".to_string())
+                        let error = format!("Failed to send HTLC: {:?}", e);
+                        debug!("{}", error);
+                        Err(error)
                     }
                 }
             }
@@ -145,13 +199,69 @@ pub fn pay(
     }
 }
 
+// Finds the route a payment to a node or invoice would take, nothing is sent
+pub fn query_route(
+    target: String,
+    amount_msat: Option<u64>,
+    final_cltv: Option<u32>,
+    channel_manager: &Arc<ChannelManager>,
+    network: &Network,
+    router: &Arc<router::Router>,
+) -> Result<RouteInfo, String> {
+    let (route_target, amt) = if target.len() == 33 * 2 {
+        let payee = hex_to_compressed_pubkey(&target).ok_or("Bad PubKey for target node".to_string())?;
+        let route_target = RouteTarget {
+            payee,
+            route_hint: Vec::new(),
+            final_cltv: final_cltv.unwrap_or(DEFAULT_FINAL_CLTV.0 as u32),
+        };
+        (route_target, amount_msat.ok_or("Amount required when routing to a node".to_string())?)
+    } else {
+        let invoice = Invoice::from_str(&target).map_err(|err| {
+            debug!("Bad invoice {:?}", err);
+            "Target must be a node id or an invoice".to_string()
+        })?;
+        if to_network(invoice.currency()) != *network {
+            return Err("Wrong network on invoice".to_string());
+        }
+        let mut route_target = route_target(&invoice)?;
+        if let Some(final_cltv) = final_cltv {
+            route_target.final_cltv = final_cltv;
+        }
+        let amt = invoice_amount_msat(&invoice)
+            .or(amount_msat)
+            .ok_or("Invoice has no amount, specify one".to_string())?;
+        (route_target, amt)
+    };
+
+    let route = find_route(&route_target, amt, channel_manager, router)?;
+    Ok(route_info(&route, amt))
+}
+
+fn route_info(route: &router::Route, amt: u64) -> RouteInfo {
+    let hops: Vec<RouteHopInfo> = route.hops.iter().map(|hop| RouteHopInfo {
+        node_id: hex_str(&hop.pubkey.serialize()),
+        short_channel_id: hop.short_channel_id,
+        fee_msat: hop.fee_msat,
+        cltv_expiry_delta: hop.cltv_expiry_delta,
+    }).collect();
+    let (total_fee_msat, total_cltv) = route_totals(route);
+    RouteInfo {
+        hops,
+        amount_msat: amt,
+        total_fee_msat,
+        total_cltv,
+    }
+}
+
 pub fn create_invoice(
-    value: String,
+    amount_pico_btc: u64,
+    description: Option<String>,
     payment_preimages: &Arc<Mutex<HashMap<PaymentHash, PaymentPreimage>>>,
     network: &Network,
     secp_ctx: &Secp256k1<All>,
     keys: &Arc<KeysManager>,
-) -> Result<String, String> {
+) -> Result<InvoiceInfo, String> {
     let mut payment_preimage = [0; 32];
     thread_rng().fill_bytes(&mut payment_preimage);
     let payment_hash = bitcoin_hashes::sha256::Hash::hash(&payment_preimage);
@@ -168,13 +278,14 @@ pub fn create_invoice(
     debug!("payment_hash: {}", hex_str(&payment_hash.into_inner()));
 
     let currency = to_currency(*network);
+    let description = description.unwrap_or_else(|| "rust-lightning-bitcoinrpc invoice".to_string());
 
     let invoice_res = lightning_invoice::InvoiceBuilder::new(currency)
         .payment_hash(payment_hash)
-        .description("rust-lightning-bitcoinrpc invoice".to_string())
+        .description(description.clone())
         //TODO: Restore routing
         //.route(chans)
-        .amount_pico_btc(value.parse::<u64>().unwrap())
+        .amount_pico_btc(amount_pico_btc)
         .current_timestamp()
         .build_signed(|msg_hash| {
             secp_ctx.sign_recoverable(msg_hash, &keys.get_node_secret())
@@ -182,7 +293,13 @@ pub fn create_invoice(
 
     match invoice_res {
         Ok(invoice) => {
-            Ok(invoice.to_string())
+            Ok(InvoiceInfo {
+                invoice: invoice.to_string(),
+                payment_hash: hex_str(&payment_hash.into_inner()),
+                payee: hex_str(&invoice.recover_payee_pub_key().serialize()),
+                amount_msat: invoice_amount_msat(&invoice),
+                description,
+            })
         }
         Err(e) => Err(format!("Error, {:#?}", e).to_string()),
     }
@@ -1,39 +1,78 @@
 pub mod channel;
+pub mod graph;
 pub mod invoice;
 pub mod peer;
 
 #[macro_export]
 macro_rules! impl_command {
     ($item:tt) => (
-        use ln_cmd::{channel, invoice, peer};
+        use ln_cmd::{channel, graph, invoice, peer};
         impl<T: Larva> channel::ChannelC for $item<T> {
-            fn fund_channel(&self, args: Vec<String>) -> Result<String, String> {
-                channel::fund_channel(args, &self.channel_manager, self.event_notify.clone())
+            fn fund_channel(&self, node_id: String, value_sat: u64, push_msat: u64, private: bool, close_to: Option<String>) -> Result<protocol::ChannelInfo, String> {
+                channel::fund_channel(node_id, value_sat, push_msat, private, close_to, &self.channel_manager, &self.monitor, &self.router, &self.channel_labels, &self.close_addresses, self.event_notify.clone())
             }
-            fn close(&self, line: String) -> Result<String, String> {
-                channel::close(line, &self.channel_manager, self.event_notify.clone())
+            fn close(&self, line: String, close_to: Option<String>) -> Result<String, String> {
+                channel::close(line, close_to, &self.channel_manager, &self.closed_channels, &self.close_addresses, &self.channel_labels, self.event_notify.clone())
+            }
+            fn force_close(&self, line: String) -> Result<(String, Option<String>), String> {
+                channel::force_close(line, &self.channel_manager, &self.monitor, &self.closed_channels, &self.chain_broadcaster, &self.channel_labels, self.event_notify.clone())
             }
             fn force_close_all(&self) {
-                channel::force_close_all(&self.channel_manager)
+                channel::force_close_all(&self.channel_manager, &self.closed_channels)
+            }
+            fn channel_list(&self, state: Option<protocol::ChannelState>, peer: Option<String>) -> Result<Vec<protocol::ChannelInfo>, String> {
+                channel::channel_list(&self.channel_manager, &self.monitor, &self.router, &self.channel_labels, state, peer)
             }
-            fn channel_list(&self, mode: &str) -> Vec<String> {
-                channel::channel_list(&self.channel_manager, mode)
+            fn closed_channel_list(&self) -> Vec<protocol::ClosedChannelInfo> {
+                channel::closed_channel_list(&self.closed_channels)
+            }
+            fn label(&self, channel: String, label: String) -> Result<(String, String), String> {
+                channel::label(channel, label, &self.channel_manager, &self.channel_labels)
             }
         }
         impl<T: Larva> invoice::InvoiceC for $item<T> {
-            fn pay(&self, args: Vec<String>) -> Result<(), String> {
-                invoice::pay(args, &self.channel_manager, self.event_notify.clone(), &self.network, &self.router)
+            fn pay(&self, invoice: String, amount_msat: Option<u64>, limits: protocol::PaymentLimits) -> Result<protocol::PaymentInfo, String> {
+                invoice::pay(invoice, amount_msat, limits, &self.channel_manager, self.event_notify.clone(), &self.network, &self.router)
+            }
+            fn create_invoice(&self, amount_pico_btc: u64, description: Option<String>) -> Result<protocol::InvoiceInfo, String> {
+                invoice::create_invoice(amount_pico_btc, description, &self.payment_preimages, &self.network, &self.secp_ctx, &self.keys)
             }
-            fn create_invoice(&self, line: String) -> Result<String, String> {
-                invoice::create_invoice(line, &self.payment_preimages, &self.network, &self.secp_ctx, &self.keys)
+            fn query_route(&self, target: String, amount_msat: Option<u64>, final_cltv: Option<u32>) -> Result<protocol::RouteInfo, String> {
+                invoice::query_route(target, amount_msat, final_cltv, &self.channel_manager, &self.network, &self.router)
+            }
+        }
+        impl<T: Larva> graph::GraphC for $item<T> {
+            fn graph_nodes(&self) -> Vec<protocol::GraphNodeInfo> {
+                graph::graph_nodes(&self.router)
+            }
+            fn graph_channels(&self) -> Vec<protocol::GraphChannelInfo> {
+                graph::graph_channels(&self.router)
+            }
+            fn graph_node(&self, node: String) -> Result<protocol::GraphNodeInfo, String> {
+                graph::graph_node(node, &self.router)
+            }
+            fn graph_channel(&self, short_channel_id: String) -> Result<protocol::GraphChannelInfo, String> {
+                graph::graph_channel(short_channel_id, &self.router)
             }
         }
         impl<T: Larva> peer::PeerC for $item<T> {
-            fn connect(&self, node: String) {
-                peer::connect(node, &self.peer_manager, self.event_notify.clone(), self.larva.clone())
+            fn connect(&self, node: String) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, String>> + Send>> {
+                Box::pin(peer::connect(node, self.peer_manager.clone(), self.connections.clone(), self.peer_store.clone(), self.event_notify.clone(), self.larva.clone()))
+            }
+            fn list(&self) -> Vec<protocol::PeerInfo> {
+                peer::list(&self.peer_manager, &self.connections, &self.channel_manager, &self.router)
+            }
+            fn disconnect(&self, node_id: String) -> Result<String, String> {
+                peer::disconnect(node_id, &self.connections, &self.peer_store)
+            }
+            fn ban(&self, target: String) -> Result<String, String> {
+                peer::ban(target, &self.connections, &self.peer_store)
+            }
+            fn unban(&self, target: String) -> Result<String, String> {
+                peer::unban(target, &self.connections, &self.peer_store)
             }
-            fn list(&self) -> Vec<String> {
-                peer::list(&self.peer_manager)
+            fn ban_list(&self) -> Vec<String> {
+                peer::ban_list(&self.connections)
             }
         }
     )
@@ -1,68 +1,154 @@
+use lightning::ln::channelmanager::ChannelManager;
+use lightning::ln::msgs::RoutingMessageHandler;
 use lightning::ln::peer_handler::PeerManager;
-use crate::ln_bridge::connection::{Connection, SocketDescriptor};
+use lightning::ln::router::Router;
+use lightning::util::ser::Writeable;
+use secp256k1::key::PublicKey;
+use protocol::PeerInfo;
+use crate::ln_bridge::ban_list::BanTarget;
+use crate::ln_bridge::connection::{self, Connection, SocketDescriptor};
+use crate::ln_bridge::connections::ConnectionRegistry;
+use crate::ln_bridge::peer_store::PeerStore;
 use crate::ln_bridge::utils::{hex_str, hex_to_compressed_pubkey};
 
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::UNIX_EPOCH;
 use futures::channel::mpsc;
 use crate::executor::Larva;
 
 pub trait PeerC {
-    fn connect(&self, node: String);
-    fn list(&self) -> Vec<String>;
+    fn connect(&self, node: String) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+    fn list(&self) -> Vec<PeerInfo>;
+    fn disconnect(&self, node_id: String) -> Result<String, String>;
+    fn ban(&self, target: String) -> Result<String, String>;
+    fn unban(&self, target: String) -> Result<String, String>;
+    fn ban_list(&self) -> Vec<String>;
 }
 
-// connect peer
-pub fn connect<T: Larva>(
+// connect peer given as pubkey@host[:port], resolves once the handshake started,
+// the address is kept for reconnecting
+pub async fn connect<T: Larva>(
     node: String,
-    peer_manager: &Arc<PeerManager<SocketDescriptor<T>>>,
+    peer_manager: Arc<PeerManager<SocketDescriptor<T>>>,
+    registry: Arc<ConnectionRegistry>,
+    peer_store: Arc<PeerStore>,
     event_notify: mpsc::Sender<()>,
     larva: T,
-) {
-    // TODO: hard code split offset
-    match hex_to_compressed_pubkey(node.split_at(0).1) {
-        Some(pk) => {
-            if node.as_bytes()[33 * 2] == '@' as u8 {
-                let parse_res: Result<std::net::SocketAddr, _> =
-                    node.split_at(33 * 2 + 1).1.parse();
-                if let Ok(addr) = parse_res {
-                    info!("Attempting to connect to {}...", addr);
-                    match std::net::TcpStream::connect_timeout(&addr, Duration::from_secs(10)) {
-                        Ok(stream) => {
-                            debug!("connected, initiating handshake!");
-                            let peer_manager = peer_manager.clone();
-                            Connection::setup_outbound(
-                                peer_manager,
-                                event_notify,
-                                pk,
-                                tokio::net::TcpStream::from_std(
-                                    stream,
-                                    &tokio_net::driver::Handle::default(),
-                                ).unwrap(),
-                                larva,
-                            );
-                        }
-                        Err(e) => {
-                            debug!("connection failed {:?}!", e);
-                        }
-                    }
-                } else {
-                    debug!("Couldn't parse host:port into a socket address");
-                }
-            } else {
-                debug!("Invalid line, should be c pubkey@host:port");
-            }
+) -> Result<String, String> {
+    let mut parts = node.splitn(2, '@');
+    let pubkey = parts.next()
+        .filter(|pubkey| pubkey.len() == 33 * 2)
+        .and_then(hex_to_compressed_pubkey)
+        .ok_or("Bad PubKey for remote node".to_string())?;
+    let address = parts.next().ok_or("Invalid line, should be pubkey@host[:port]".to_string())?;
+    let (host, port) = connection::parse_host_port(address)?;
+    let address = connection::format_host_port(&host, port);
+
+    // an explicit connect lifts a previous disconnect
+    peer_store.resume(&pubkey);
+    Connection::connect_address(peer_manager, registry, event_notify, pubkey, address.clone(), larva).await?;
+    debug!("connected, initiating handshake!");
+    peer_store.add(&pubkey, address);
+    Ok(hex_str(&pubkey.serialize()))
+}
+
+
+// Feature bits of every node_announcement we have, by node id
+fn announced_features(router: &Arc<Router>) -> HashMap<PublicKey, String> {
+    let mut features = HashMap::new();
+    let mut starting_point = None;
+    loop {
+        let batch = router.get_next_node_announcements(starting_point.as_ref(), 255);
+        if batch.is_empty() {
+            return features;
         }
-        None => debug!("Bad PubKey for remote node"),
+        for announcement in batch.iter() {
+            // skip the u16 length prefix
+            let encoded = announcement.contents.features.encode();
+            features.insert(announcement.contents.node_id, hex_str(&encoded[2..]));
+        }
+        starting_point = batch.last().map(|announcement| announcement.contents.node_id);
     }
 }
 
+// connected peers, those still in the handshake aren't listed
+pub fn list<T: Larva>(
+    peer_manager: &Arc<PeerManager<SocketDescriptor<T>>>,
+    registry: &Arc<ConnectionRegistry>,
+    channel_manager: &Arc<ChannelManager>,
+    router: &Arc<Router>,
+) -> Vec<PeerInfo> {
+    let node_ids = peer_manager.get_peer_node_ids();
+    let channels = channel_manager.list_channels();
+    let features = announced_features(router);
+    registry.list().into_iter().filter_map(|conn| {
+        let node_id = conn.node_id.filter(|node_id| node_ids.contains(node_id))?;
+        Some(PeerInfo {
+            node_id: hex_str(&node_id.serialize()),
+            address: conn.target.or_else(|| conn.addr.map(|addr| addr.to_string())),
+            inbound: conn.inbound,
+            connection_id: conn.id,
+            connected_since: conn.connected_since.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()),
+            bytes_sent: conn.bytes_sent,
+            bytes_received: conn.bytes_received,
+            channels: channels.iter()
+                .filter(|channel| channel.remote_network_id == node_id)
+                .map(|channel| hex_str(&channel.channel_id))
+                .collect(),
+            features: features.get(&node_id).cloned(),
+        })
+    }).collect()
+}
+
+// drop all connections to a peer, we don't reconnect until `peer connect` or unban but it
+// may connect to us
+pub fn disconnect(node_id: String, registry: &Arc<ConnectionRegistry>, peer_store: &Arc<PeerStore>) -> Result<String, String> {
+    let pubkey = match BanTarget::parse(&node_id)? {
+        BanTarget::Node(pubkey) => pubkey,
+        BanTarget::Ip(_) => return Err("Expected a node id".to_string()),
+    };
+    if registry.disconnect_node(&pubkey) {
+        peer_store.suspend(&pubkey);
+        info!("Disconnecting {}", &node_id);
+        Ok(node_id)
+    } else {
+        Err(format!("Not connected to {}", &node_id))
+    }
+}
+
+// ban a node id or IP address, drop its connections and its stored addresses
+pub fn ban(target: String, registry: &Arc<ConnectionRegistry>, peer_store: &Arc<PeerStore>) -> Result<String, String> {
+    let ban_target = BanTarget::parse(&target)?;
+    registry.ban_list().ban(&ban_target);
+    match ban_target {
+        BanTarget::Node(pubkey) => {
+            registry.disconnect_node(&pubkey);
+            peer_store.forget(Some(&pubkey), None);
+        }
+        BanTarget::Ip(ip) => {
+            registry.disconnect_ip(&ip);
+            peer_store.forget(None, Some(&ip));
+        }
+    };
+    info!("Banned {}", &target);
+    Ok(target)
+}
+
+pub fn unban(target: String, registry: &Arc<ConnectionRegistry>, peer_store: &Arc<PeerStore>) -> Result<String, String> {
+    let ban_target = BanTarget::parse(&target)?;
+    if let BanTarget::Node(pubkey) = &ban_target {
+        peer_store.resume(pubkey);
+    }
+    if registry.ban_list().unban(&ban_target) {
+        Ok(target)
+    } else {
+        Err(format!("{} is not banned", &target))
+    }
+}
 
-pub fn list<T: Larva>(peer_manager: &Arc<PeerManager<SocketDescriptor<T>>>) -> Vec<String>{
-    peer_manager
-        .get_peer_node_ids()
-        .into_iter()
-        .map(|node_id| {
-            hex_str(&node_id.serialize())
-        }).collect()
+pub fn ban_list(registry: &Arc<ConnectionRegistry>) -> Vec<String> {
+    registry.ban_list().list()
 }
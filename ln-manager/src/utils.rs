@@ -28,3 +28,12 @@ pub fn compact_btc_to_bech32(btc_network: Network) -> bitcoin_bech32::constants:
         Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
     }
 }
+
+// as bitcoind names it in getblockchaininfo
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "main",
+        Network::Testnet => "test",
+        Network::Regtest => "regtest",
+    }
+}
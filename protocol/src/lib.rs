@@ -1,157 +1,685 @@
-use bincode;
-use std::str::FromStr;
+use bincode::{self, Options};
+use std::io::{self, Read, Write};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+mod v3;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum RequestFuncs {
     GetAddresses,
     GetNodeInfo,
-    PeerConnect(String),
-    ChannelCreate(Vec<String>),
-    ChannelClose(String),
+    // pubkey@host[:port]
+    PeerConnect { node: String },
+    // close_to is the address a cooperative close is swept to once it confirms
+    ChannelCreate { node_id: String, value_sat: u64, push_msat: u64, private: bool, close_to: Option<String> },
+    // channels are given as channel id, short channel id, funding outpoint or label. The closing
+    // tx pays to our node wallet, a cooperative close is swept to close_to once it confirms
+    // (second fee), other closes are not redirected
+    ChannelClose { channel: String, close_to: Option<String> },
+    ChannelForceClose { channel: String },
     ChannelCloseAll,
-    ChannelList(String),
+    // no state lists channels in every state, peer is a node id or alias
+    ChannelList { state: Option<ChannelState>, peer: Option<String> },
+    ChannelListClosed,
+    ChannelLabel { channel: String, label: String },
     PeerList,
-    InvoiceCreate(String),
-    InvoicePay(Vec<String>),
+    PeerDisconnect { node_id: String },
+    // node id or IP address
+    PeerBan { target: String },
+    PeerUnban { target: String },
+    PeerBanList,
+    InvoiceCreate { amount_pico_btc: u64, description: Option<String> },
+    // amount_msat is only needed for invoices without an amount
+    InvoicePay {
+        invoice: String,
+        amount_msat: Option<u64>,
+        #[serde(default)]
+        limits: PaymentLimits,
+    },
+    // target is a node id or invoice, amount_msat is optional for invoices with an amount
+    QueryRoute { target: String, amount_msat: Option<u64>, final_cltv: Option<u32> },
+    GraphNodes,
+    GraphChannels,
+    // node id or alias
+    GraphNode { node: String },
+    // BLOCKxTXxOUT
+    GraphChannel { short_channel_id: String },
+    // answered with a ResponseFuncs::Event per event until the client disconnects
+    SubscribeEvents,
+}
+
+// Fee and timelock budget for a payment, it fails rather than exceed any of them
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct PaymentLimits {
+    pub max_fee_msat: Option<u64>,
+    // of the amount paid
+    pub max_fee_percent: Option<f64>,
+    pub max_total_cltv: Option<u32>,
+}
+
+// What a token must grant for a request to be dispatched
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum Permission {
+    // queries that don't change anything
+    Read,
+    // creating invoices
+    Invoice,
+    // anything that moves funds or changes channels and peers
+    Admin,
+}
+
+impl RequestFuncs {
+    pub fn permission(&self) -> Permission {
+        match self {
+            RequestFuncs::GetAddresses
+            | RequestFuncs::GetNodeInfo
+            | RequestFuncs::ChannelList { .. }
+            | RequestFuncs::ChannelListClosed
+            | RequestFuncs::PeerList
+            | RequestFuncs::PeerBanList
+            | RequestFuncs::QueryRoute { .. }
+            | RequestFuncs::GraphNodes
+            | RequestFuncs::GraphChannels
+            | RequestFuncs::GraphNode { .. }
+            | RequestFuncs::GraphChannel { .. }
+            | RequestFuncs::SubscribeEvents => Permission::Read,
+            RequestFuncs::InvoiceCreate { .. } => Permission::Invoice,
+            RequestFuncs::PeerConnect { .. }
+            | RequestFuncs::ChannelCreate { .. }
+            | RequestFuncs::ChannelClose { .. }
+            | RequestFuncs::ChannelForceClose { .. }
+            | RequestFuncs::ChannelCloseAll
+            | RequestFuncs::ChannelLabel { .. }
+            | RequestFuncs::PeerDisconnect { .. }
+            | RequestFuncs::PeerBan { .. }
+            | RequestFuncs::PeerUnban { .. }
+            | RequestFuncs::InvoicePay { .. } => Permission::Admin,
+        }
+    }
+
+    // Oldest protocol version that can carry the request and its answer without dropping anything
+    pub fn min_version(&self) -> u16 {
+        match self {
+            RequestFuncs::GetNodeInfo => 4,
+            _ => MIN_PROTOCOL_VERSION,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum ResponseFuncs {
     GetAddresses(Vec<String>),
-    GetNodeInfo(String),
-    PeerConnect,
-    ChannelCreate(String),
+    GetNodeInfo(Box<NodeInfo>),
+    // node id
+    PeerConnect(String),
+    // the channel as it is while we wait for the peer to accept it
+    ChannelCreate(ChannelInfo),
+    // channel id
     ChannelClose(String),
+    // channel id, commitment txid
+    ChannelForceClose(String, Option<String>),
     ChannelCloseAll,
-    ChannelList(Vec<String>),
-    PeerList(Vec<String>),
-    InvoiceCreate(String),
-    InvoicePay,
+    ChannelList(Vec<ChannelInfo>),
+    ChannelListClosed(Vec<ClosedChannelInfo>),
+    // channel id, label
+    ChannelLabel(String, String),
+    PeerList(Vec<PeerInfo>),
+    PeerDisconnect(String),
+    PeerBan(String),
+    PeerUnban(String),
+    PeerBanList(Vec<String>),
+    InvoiceCreate(InvoiceInfo),
+    // the payment is on its way, NodeEvent::PaymentSent or PaymentFailed tells how it went
+    InvoicePay(PaymentInfo),
+    QueryRoute(RouteInfo),
+    GraphNodes(Vec<GraphNodeInfo>),
+    GraphChannels(Vec<GraphChannelInfo>),
+    GraphNode(GraphNodeInfo),
+    GraphChannel(GraphChannelInfo),
+    Event(NodeEvent),
     Error(String),
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub alias: String,
+    // main, test or regtest
+    pub network: String,
+    pub port: u16,
+    pub listen_addresses: Vec<String>,
+    // announced in node_announcement
+    pub addresses: Vec<String>,
+    // last block the node processed
+    pub block_height: u32,
+    pub block_hash: String,
+    // every block bitcoind knows about was processed and bitcoind isn't in initial block download
+    pub synced_to_chain: bool,
+    pub num_active_channels: u32,
+    pub num_pending_channels: u32,
+    pub num_inactive_channels: u32,
+    pub num_peers: u32,
+    // summed over usable channels, what we could send and receive right now. Channel reserves
+    // and pending HTLCs are excluded, so this isn't our share of the channel value
+    pub spendable_msat: u64,
+    pub receivable_msat: u64,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct InvoiceInfo {
+    // BOLT 11 encoded
+    pub invoice: String,
+    pub payment_hash: String,
+    pub payee: String,
+    pub amount_msat: Option<u64>,
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PaymentInfo {
+    pub payment_hash: String,
+    pub destination: String,
+    pub route: RouteInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum ChannelState {
+    // funding transaction not yet confirmed, no short channel id
+    Pending,
+    // confirmed and usable for payments
+    Active,
+    // confirmed but peer offline or channel shutting down
+    Inactive,
+}
+
+// Our reserve and pending HTLCs aren't reported on their own, rust-lightning's ChannelDetails
+// doesn't expose them. unavailable_msat is their sum together with the remote reserve and the
+// commitment fee.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ChannelInfo {
+    pub id: String,
+    pub label: Option<String>,
+    pub short_channel_id: Option<u64>,
+    pub peer: String,
+    pub user_id: u64,
+    pub state: ChannelState,
+    pub is_usable: bool,
+    pub is_public: bool,
+    pub funding_txo: Option<String>,
+    pub value_sats: u64,
+    pub outbound_capacity_msat: u64,
+    pub inbound_capacity_msat: u64,
+    // spendable by neither side
+    pub unavailable_msat: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PeerInfo {
+    pub node_id: String,
+    // the address we dialed for outbound connections, the remote socket for inbound ones
+    pub address: Option<String>,
+    pub inbound: bool,
+    pub connection_id: u64,
+    // unix timestamp
+    pub connected_since: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    // ids of channels we have with the peer
+    pub channels: Vec<String>,
+    // hex feature bits from the peer's node_announcement, None if we have none. The features
+    // sent in init aren't exposed by rust-lightning's PeerManager
+    pub features: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum CloseType {
+    Cooperative,
+    LocalForce,
+    RemoteForce,
+    // remote broadcast a revoked commitment and we claimed it
+    Breach,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ClosedChannelInfo {
+    pub id: String,
+    pub short_channel_id: Option<u64>,
+    pub peer: Option<String>,
+    pub funding_txo: Option<String>,
+    // unknown until the closing transaction is seen on chain, unless we initiated the close
+    pub close_type: Option<CloseType>,
+    pub closing_txid: Option<String>,
+    // what each side could send when we last saw the channel open, not what the close paid out
+    #[serde(alias = "local_balance_msat")]
+    pub last_outbound_capacity_msat: u64,
+    #[serde(alias = "remote_balance_msat")]
+    pub last_inbound_capacity_msat: u64,
+    pub closed_at_height: Option<u32>,
+    pub spendable_at_height: Option<u32>,
+}
+
+// Announced node as known to our router
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct GraphNodeInfo {
+    pub node_id: String,
+    pub alias: String,
+    // #rrggbb
+    pub color: String,
+    pub addresses: Vec<String>,
+    // unix timestamp of the node_announcement
+    pub last_update: u32,
+}
+
+// Forwarding policy one side of a channel announced in its channel_update
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RoutingPolicy {
+    pub fee_base_msat: u32,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u16,
+    pub htlc_minimum_msat: u64,
+    pub disabled: bool,
+    // unix timestamp of the channel_update
+    pub last_update: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct GraphChannelInfo {
+    pub short_channel_id: u64,
+    pub node1: String,
+    pub node2: String,
+    // policy for forwarding from node1 to node2
+    pub node1_policy: RoutingPolicy,
+    pub node2_policy: RoutingPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RouteHopInfo {
+    pub node_id: String,
+    pub short_channel_id: u64,
+    // fee charged by this hop, the payment amount on the last hop
+    pub fee_msat: u64,
+    // cltv delta added by this hop, the final cltv on the last hop
+    pub cltv_expiry_delta: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RouteInfo {
+    pub hops: Vec<RouteHopInfo>,
+    pub amount_msat: u64,
+    pub total_fee_msat: u64,
+    // blocks our funds are locked for at most, relative to the current height
+    pub total_cltv: u32,
+}
+
+// Pushed to subscribed clients as things happen on the node, hashes and ids are hex
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum NodeEvent {
+    // an incoming payment was claimed
+    PaymentReceived { payment_hash: String, amount_msat: u64 },
+    PaymentSent { payment_hash: String, payment_preimage: String },
+    // rejected_by_dest is false when an intermediate hop failed the payment
+    PaymentFailed { payment_hash: String, rejected_by_dest: bool },
+    FundingBroadcast { funding_txo: String },
+    // funding transaction confirmed, the channel has its short channel id
+    ChannelOpened { channel_id: String, peer: String, short_channel_id: u64, value_sats: u64 },
+    // the transaction spending the funding output confirmed, force closes have a close_type other
+    // than Cooperative
+    ChannelClosed { channel_id: String, peer: Option<String>, close_type: CloseType, closing_txid: String, height: u32 },
+    // on-chain outputs of closed channels we can spend, txid:vout
+    SpendableOutputs { outpoints: Vec<String> },
+    PeerConnected { node_id: String },
+    PeerDisconnected { node_id: String },
+    NewBlock { height: u32, hash: String },
+}
+
+impl NodeEvent {
+    // variant name, as used to filter events
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeEvent::PaymentReceived { .. } => "PaymentReceived",
+            NodeEvent::PaymentSent { .. } => "PaymentSent",
+            NodeEvent::PaymentFailed { .. } => "PaymentFailed",
+            NodeEvent::FundingBroadcast { .. } => "FundingBroadcast",
+            NodeEvent::ChannelOpened { .. } => "ChannelOpened",
+            NodeEvent::ChannelClosed { .. } => "ChannelClosed",
+            NodeEvent::SpendableOutputs { .. } => "SpendableOutputs",
+            NodeEvent::PeerConnected { .. } => "PeerConnected",
+            NodeEvent::PeerDisconnected { .. } => "PeerDisconnected",
+            NodeEvent::NewBlock { .. } => "NewBlock",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Message {
-    Request(RequestFuncs),
+    // access token, request
+    Request(String, RequestFuncs),
     Response(ResponseFuncs),
+    // the request couldn't be dispatched, failures on the node are ResponseFuncs::Error
+    Error(ErrorCode, String),
 }
 
-pub fn serialize_message(msg: Message) -> Vec<u8> {
-    bincode::serialize(&msg).expect("Could not serialize message")
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum ErrorCode {
+    // the frame couldn't be decoded
+    Malformed,
+    // missing or unknown token, or one without the permission the request needs
+    Unauthorized,
+    // decoded fine but not something the receiver answers
+    InvalidRequest,
 }
 
-pub fn deserialize_message(v: Vec<u8>) -> Message {
-    bincode::deserialize(&v).expect("Could not deserialize message")
+// Versions of the envelope layout this build speaks. Bump PROTOCOL_VERSION when Message
+// changes and keep the previous layout in its own module, MIN_PROTOCOL_VERSION stays one
+// version behind.
+pub const PROTOCOL_VERSION: u16 = 4;
+pub const MIN_PROTOCOL_VERSION: u16 = 3;
+
+// Every frame body starts with a big endian version and request id. This part never changes,
+// version 0 is reserved for Envelope::Unsupported.
+pub const ENVELOPE_HEADER_SIZE: usize = 10;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Envelope {
+    Message { version: u16, request_id: u64, message: Message },
+    // the versions the sender speaks, the answer to a message in any other version
+    Unsupported { request_id: u64, min_version: u16, max_version: u16 },
 }
 
-pub struct ProtocalParseError {
-    pub msg: String
+#[derive(Debug, PartialEq, Clone)]
+pub enum DecodeError {
+    Malformed(String),
+    UnsupportedVersion { version: u16, request_id: u64 },
 }
 
-impl FromStr for RequestFuncs {
-    type Err = ProtocalParseError; 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let cmd_value: Vec<&str> = s.split(',').collect();
-        let cmd = cmd_value[0];
-        let sub_command = cmd_value[1];
-        match cmd {
-            "info" => {
-                 match sub_command {
-                    "addresses" => {
-                        Ok(RequestFuncs::GetAddresses)
-                    }
-                    "node" => {
-                        Ok(RequestFuncs::GetNodeInfo)
-                    }
-                    _ => {
-                        Err(ProtocalParseError{ msg: String::from("Invalid Argument") })
-                    }
-                }
-            }
-            "peer" => {
-                match sub_command {
-                    "connect" => {
-                        let value = cmd_value[2].to_string();
-                        Ok(RequestFuncs::PeerConnect(value))
-                    }
-                    "list" => {
-                        Ok(RequestFuncs::PeerList)
-                    }
-                    _ => {
-                        Err(ProtocalParseError{ msg: String::from("Invalid Argument") })
-                    }
-                }
-            }
-            "channel" => {
-                match sub_command {
-                    "create" => {
-                        if cmd_value.len() != 5 {
-                            return Err(ProtocalParseError{ msg: String::from("Insufficient Arguments") });
-                        }
-                        let args: Vec<String> = cmd_value[2..]
-                            .into_iter()
-                            .map(|v| {
-                                v.to_string()
-                            }).collect();
-                        Ok(RequestFuncs::ChannelCreate(args))
-                    }
-                    "kill" => {
-                        if cmd_value.len() != 3 {
-                            return Err(ProtocalParseError{ msg: String::from("Insufficient Arguments") });
-                        }
-                        let channel = cmd_value[2].to_string();
-                        Ok(RequestFuncs::ChannelClose(channel))
-                    }
-                    "killall" => {
-                        Ok(RequestFuncs::ChannelCloseAll)
-                    }
-                    "list" => {
-                        if cmd_value.len() != 3 {
-                            return Err(ProtocalParseError{ msg: String::from("Insufficient Arguments") });
-                        }
-                        let mode = cmd_value[2].to_string();
-                        Ok(RequestFuncs::ChannelList(mode))
-                    }
-                    _ => {
-                        Err(ProtocalParseError{ msg: String::from("Invalid Argument") })
-                    }
-                }
-            }
-            "invoice" => {
-                match sub_command {
-                    "create" =>  {
-                        let value = cmd_value[2].to_string();
-                        Ok(RequestFuncs::InvoiceCreate(value))
-                    }
-                    "pay" => {
-                        let args: Vec<String> = cmd_value[2..]
-                            .into_iter()
-                            .map(|v| {
-                                v.to_string()
-                            }).collect();
-                        Ok(RequestFuncs::InvoicePay(args))
-                    }
-                    _ => {
-                        Err(ProtocalParseError{ msg: String::from("Invalid Argument") })
-                    }
-                }
-            }
-            _ => {
-                Err(ProtocalParseError{ msg: String::from("Invalid Command") })
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DecodeError::Malformed(e) => write!(f, "Malformed message: {}", e),
+            DecodeError::UnsupportedVersion { version, .. } => write!(f, "Unsupported protocol version {}", version),
+        }
+    }
+}
+
+// Highest version both sides speak, given the other side's range
+pub fn negotiate(min_version: u16, max_version: u16) -> Option<u16> {
+    let version = std::cmp::min(max_version, PROTOCOL_VERSION);
+    if version >= std::cmp::max(min_version, MIN_PROTOCOL_VERSION) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+pub fn serialize_message(msg: &Message) -> Vec<u8> {
+    bincode::serialize(msg).expect("Could not serialize message")
+}
+
+// lengths inside the payload can't make us allocate more than the payload itself
+fn deserialize<T: DeserializeOwned>(payload: &[u8]) -> Result<T, DecodeError> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(payload.len() as u64)
+        .deserialize(payload)
+        .map_err(|e| DecodeError::Malformed(e.to_string()))
+}
+
+pub fn deserialize_message(payload: &[u8]) -> Result<Message, DecodeError> {
+    deserialize(payload)
+}
+
+pub fn encode_envelope(envelope: &Envelope) -> Vec<u8> {
+    let mut body = Vec::new();
+    match envelope {
+        Envelope::Message { version, request_id, message } => {
+            body.extend_from_slice(&version.to_be_bytes());
+            body.extend_from_slice(&request_id.to_be_bytes());
+            // older versions are bincode of their own Message layout
+            match version {
+                3 => body.extend_from_slice(&bincode::serialize(&v3::Message::from(message.clone())).expect("Could not serialize message")),
+                _ => body.extend_from_slice(&serialize_message(message)),
             }
         }
+        Envelope::Unsupported { request_id, min_version, max_version } => {
+            body.extend_from_slice(&0u16.to_be_bytes());
+            body.extend_from_slice(&request_id.to_be_bytes());
+            body.extend_from_slice(&min_version.to_be_bytes());
+            body.extend_from_slice(&max_version.to_be_bytes());
+        }
+    }
+    body
+}
+
+pub fn decode_envelope(body: &[u8]) -> Result<Envelope, DecodeError> {
+    if body.len() < ENVELOPE_HEADER_SIZE {
+        return Err(DecodeError::Malformed(format!("{} bytes are too short for an envelope", body.len())));
+    }
+    let version = u16::from_be_bytes([body[0], body[1]]);
+    let mut request_id = [0u8; 8];
+    request_id.copy_from_slice(&body[2..ENVELOPE_HEADER_SIZE]);
+    let request_id = u64::from_be_bytes(request_id);
+    let payload = &body[ENVELOPE_HEADER_SIZE..];
+
+    if version == 0 {
+        if payload.len() != 4 {
+            return Err(DecodeError::Malformed("Version range must be 4 bytes".to_string()));
+        }
+        return Ok(Envelope::Unsupported {
+            request_id,
+            min_version: u16::from_be_bytes([payload[0], payload[1]]),
+            max_version: u16::from_be_bytes([payload[2], payload[3]]),
+        });
+    }
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        return Err(DecodeError::UnsupportedVersion { version, request_id });
     }
+    let message = match version {
+        3 => Message::try_from(deserialize::<v3::Message>(payload)?).map_err(DecodeError::Malformed)?,
+        _ => deserialize_message(payload)?,
+    };
+    Ok(Envelope::Message { version, request_id, message })
+}
+
+// Frames are a 4 byte big endian length followed by the encoded envelope
+pub const FRAME_HEADER_SIZE: usize = 4;
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+pub fn encode_frame(envelope: &Envelope) -> Vec<u8> {
+    let body = encode_envelope(envelope);
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+pub fn frame_len(header: [u8; FRAME_HEADER_SIZE]) -> io::Result<usize> {
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Frame of {} bytes is too large", len)));
+    }
+    Ok(len)
+}
+
+pub fn write_frame<W: Write>(w: &mut W, envelope: &Envelope) -> io::Result<()> {
+    w.write_all(&encode_frame(envelope))?;
+    w.flush()
+}
+
+// Reads the next frame, envelopes that don't decode are InvalidData errors
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Envelope> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    r.read_exact(&mut header)?;
+    let mut body = vec![0u8; frame_len(header)?];
+    r.read_exact(&mut body)?;
+    decode_envelope(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+// Short channel ids as shown by block explorers, BLOCKxTXxOUT
+pub fn short_channel_id_to_string(short_channel_id: u64) -> String {
+    format!("{}x{}x{}", short_channel_id >> 40, (short_channel_id >> 16) & 0xff_ffff, short_channel_id & 0xffff)
+}
+
+pub fn parse_short_channel_id(s: &str) -> Option<u64> {
+    let parts: Vec<&str> = s.split('x').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let block: u64 = parts[0].parse().ok()?;
+    let tx: u64 = parts[1].parse().ok()?;
+    let out: u64 = parts[2].parse().ok()?;
+    if block > 0xff_ffff || tx > 0xff_ffff || out > 0xffff {
+        return None;
+    }
+    Some(block << 40 | tx << 16 | out)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    fn request(version: u16) -> Envelope {
+        Envelope::Message {
+            version,
+            request_id: 7,
+            message: Message::Request("token".to_string(), RequestFuncs::GetNodeInfo),
+        }
+    }
+
     #[test]
     fn it_works() {
-        let a = Message::Request(RequestFuncs::PrintSomething("".to_string()));
-        let ser = serialize_message(a.clone());
-        let der = deserialize_message(ser);
+        let a = Message::Request("token".to_string(), RequestFuncs::GetNodeInfo);
+        let ser = serialize_message(&a);
+        let der = deserialize_message(&ser).unwrap();
         assert_eq!(a, der);
     }
+
+    #[test]
+    fn envelope_roundtrip() {
+        let a = request(PROTOCOL_VERSION);
+        assert_eq!(decode_envelope(&encode_envelope(&a)).unwrap(), a);
+        let unsupported = Envelope::Unsupported { request_id: 7, min_version: 1, max_version: 3 };
+        assert_eq!(decode_envelope(&encode_envelope(&unsupported)).unwrap(), unsupported);
+    }
+
+    #[test]
+    fn frame_roundtrip() {
+        let a = Envelope::Message {
+            version: PROTOCOL_VERSION,
+            request_id: 1,
+            message: Message::Response(ResponseFuncs::PeerBanList(vec!["127.0.0.1".to_string()])),
+        };
+        let frame = encode_frame(&a);
+        assert_eq!(frame.len(), FRAME_HEADER_SIZE + encode_envelope(&a).len());
+        let mut stream = io::Cursor::new([frame.clone(), frame].concat());
+        assert_eq!(read_frame(&mut stream).unwrap(), a);
+        assert_eq!(read_frame(&mut stream).unwrap(), a);
+        assert_eq!(read_frame(&mut stream).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn frame_too_large() {
+        let header = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+        assert_eq!(frame_len(header).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        match decode_envelope(&[1, 2, 3]) {
+            Err(DecodeError::Malformed(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        // valid header, the payload claims a string far longer than the frame
+        let mut body = encode_envelope(&request(PROTOCOL_VERSION))[..ENVELOPE_HEADER_SIZE].to_vec();
+        body.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        match decode_envelope(&body) {
+            Err(DecodeError::Malformed(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut stream = io::Cursor::new(vec![0, 0, 0, 3, 1, 2, 3]);
+        assert_eq!(read_frame(&mut stream).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn unknown_version_keeps_request_id() {
+        let body = encode_envelope(&request(PROTOCOL_VERSION + 1));
+        assert_eq!(
+            decode_envelope(&body).unwrap_err(),
+            DecodeError::UnsupportedVersion { version: PROTOCOL_VERSION + 1, request_id: 7 }
+        );
+    }
+
+    #[test]
+    fn typed_request_roundtrip() {
+        let requests = vec![
+            RequestFuncs::InvoiceCreate { amount_pico_btc: 10_000, description: Some("coffee, two sugars".to_string()) },
+            RequestFuncs::InvoicePay {
+                invoice: "lnbcrt1".to_string(),
+                amount_msat: None,
+                limits: PaymentLimits { max_fee_msat: Some(1000), max_fee_percent: Some(0.5), max_total_cltv: None },
+            },
+            RequestFuncs::ChannelList { state: Some(ChannelState::Pending), peer: None },
+        ];
+        for req in requests {
+            let a = Envelope::Message {
+                version: PROTOCOL_VERSION,
+                request_id: 3,
+                message: Message::Request("token".to_string(), req),
+            };
+            assert_eq!(decode_envelope(&encode_envelope(&a)).unwrap(), a);
+        }
+    }
+
+    #[test]
+    fn previous_version() {
+        let answer = |resp| Envelope::Message { version: MIN_PROTOCOL_VERSION, request_id: 4, message: Message::Response(resp) };
+        let a = answer(ResponseFuncs::PeerBanList(vec!["127.0.0.1".to_string()]));
+        assert_eq!(decode_envelope(&encode_envelope(&a)).unwrap(), a);
+        assert_eq!(RequestFuncs::PeerBanList.min_version(), MIN_PROTOCOL_VERSION);
+        assert_eq!(RequestFuncs::GetNodeInfo.min_version(), PROTOCOL_VERSION);
+
+        // version 3 node info was only the node id
+        let b = answer(ResponseFuncs::GetNodeInfo(Box::new(NodeInfo {
+            node_id: "02aa".to_string(),
+            alias: "rustbolt".to_string(),
+            network: "regtest".to_string(),
+            port: 9735,
+            listen_addresses: vec![],
+            addresses: vec![],
+            block_height: 100,
+            block_hash: "00".to_string(),
+            synced_to_chain: true,
+            num_active_channels: 0,
+            num_pending_channels: 0,
+            num_inactive_channels: 0,
+            num_peers: 0,
+            spendable_msat: 0,
+            receivable_msat: 0,
+            version: "0.1.0".to_string(),
+        })));
+        match decode_envelope(&encode_envelope(&b)) {
+            Err(DecodeError::Malformed(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION), Some(PROTOCOL_VERSION));
+        // a newer peer that still speaks our version
+        assert_eq!(negotiate(PROTOCOL_VERSION, PROTOCOL_VERSION + 5), Some(PROTOCOL_VERSION));
+        assert_eq!(negotiate(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 5), None);
+        assert_eq!(negotiate(0, MIN_PROTOCOL_VERSION - 1), None);
+    }
+
+    #[test]
+    fn short_channel_id_roundtrip() {
+        let short_channel_id = parse_short_channel_id("539268x845x1").unwrap();
+        assert_eq!(short_channel_id, 539268 << 40 | 845 << 16 | 1);
+        assert_eq!(short_channel_id_to_string(short_channel_id), "539268x845x1");
+        assert_eq!(parse_short_channel_id("539268x845"), None);
+        assert_eq!(parse_short_channel_id("16777216x0x0"), None);
+    }
 }
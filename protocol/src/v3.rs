@@ -0,0 +1,138 @@
+// Message layout of protocol version 3, still decoded and answered in. Node info was only the
+// node id, everything else is unchanged. Variants have to stay in their original order, bincode
+// encodes the index.
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ChannelInfo, ClosedChannelInfo, ErrorCode, GraphChannelInfo, GraphNodeInfo, InvoiceInfo, NodeEvent, PaymentInfo,
+    PeerInfo, RouteInfo,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ResponseFuncs {
+    GetAddresses(Vec<String>),
+    GetNodeInfo(NodeInfo),
+    PeerConnect(String),
+    ChannelCreate(ChannelInfo),
+    ChannelClose(String),
+    ChannelForceClose(String, Option<String>),
+    ChannelCloseAll,
+    ChannelList(Vec<ChannelInfo>),
+    ChannelListClosed(Vec<ClosedChannelInfo>),
+    ChannelLabel(String, String),
+    PeerList(Vec<PeerInfo>),
+    PeerDisconnect(String),
+    PeerBan(String),
+    PeerUnban(String),
+    PeerBanList(Vec<String>),
+    InvoiceCreate(InvoiceInfo),
+    InvoicePay(PaymentInfo),
+    QueryRoute(RouteInfo),
+    GraphNodes(Vec<GraphNodeInfo>),
+    GraphChannels(Vec<GraphChannelInfo>),
+    GraphNode(GraphNodeInfo),
+    GraphChannel(GraphChannelInfo),
+    Event(NodeEvent),
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    Request(String, crate::RequestFuncs),
+    Response(ResponseFuncs),
+    Error(ErrorCode, String),
+}
+
+// a node id doesn't make a full node info, see crate::RequestFuncs::min_version
+impl TryFrom<ResponseFuncs> for crate::ResponseFuncs {
+    type Error = String;
+
+    fn try_from(resp: ResponseFuncs) -> Result<Self, String> {
+        Ok(match resp {
+            ResponseFuncs::GetAddresses(addrs) => crate::ResponseFuncs::GetAddresses(addrs),
+            ResponseFuncs::GetNodeInfo(_) => return Err("Node info needs protocol version 4".to_string()),
+            ResponseFuncs::PeerConnect(node_id) => crate::ResponseFuncs::PeerConnect(node_id),
+            ResponseFuncs::ChannelCreate(c) => crate::ResponseFuncs::ChannelCreate(c),
+            ResponseFuncs::ChannelClose(id) => crate::ResponseFuncs::ChannelClose(id),
+            ResponseFuncs::ChannelForceClose(id, txid) => crate::ResponseFuncs::ChannelForceClose(id, txid),
+            ResponseFuncs::ChannelCloseAll => crate::ResponseFuncs::ChannelCloseAll,
+            ResponseFuncs::ChannelList(channels) => crate::ResponseFuncs::ChannelList(channels),
+            ResponseFuncs::ChannelListClosed(channels) => crate::ResponseFuncs::ChannelListClosed(channels),
+            ResponseFuncs::ChannelLabel(id, label) => crate::ResponseFuncs::ChannelLabel(id, label),
+            ResponseFuncs::PeerList(peers) => crate::ResponseFuncs::PeerList(peers),
+            ResponseFuncs::PeerDisconnect(node_id) => crate::ResponseFuncs::PeerDisconnect(node_id),
+            ResponseFuncs::PeerBan(target) => crate::ResponseFuncs::PeerBan(target),
+            ResponseFuncs::PeerUnban(target) => crate::ResponseFuncs::PeerUnban(target),
+            ResponseFuncs::PeerBanList(banned) => crate::ResponseFuncs::PeerBanList(banned),
+            ResponseFuncs::InvoiceCreate(invoice) => crate::ResponseFuncs::InvoiceCreate(invoice),
+            ResponseFuncs::InvoicePay(payment) => crate::ResponseFuncs::InvoicePay(payment),
+            ResponseFuncs::QueryRoute(route) => crate::ResponseFuncs::QueryRoute(route),
+            ResponseFuncs::GraphNodes(nodes) => crate::ResponseFuncs::GraphNodes(nodes),
+            ResponseFuncs::GraphChannels(channels) => crate::ResponseFuncs::GraphChannels(channels),
+            ResponseFuncs::GraphNode(node) => crate::ResponseFuncs::GraphNode(node),
+            ResponseFuncs::GraphChannel(channel) => crate::ResponseFuncs::GraphChannel(channel),
+            ResponseFuncs::Event(event) => crate::ResponseFuncs::Event(event),
+            ResponseFuncs::Error(e) => crate::ResponseFuncs::Error(e),
+        })
+    }
+}
+
+impl From<crate::ResponseFuncs> for ResponseFuncs {
+    fn from(resp: crate::ResponseFuncs) -> Self {
+        match resp {
+            crate::ResponseFuncs::GetAddresses(addrs) => ResponseFuncs::GetAddresses(addrs),
+            crate::ResponseFuncs::GetNodeInfo(info) => ResponseFuncs::GetNodeInfo(NodeInfo { node_id: info.node_id }),
+            crate::ResponseFuncs::PeerConnect(node_id) => ResponseFuncs::PeerConnect(node_id),
+            crate::ResponseFuncs::ChannelCreate(c) => ResponseFuncs::ChannelCreate(c),
+            crate::ResponseFuncs::ChannelClose(id) => ResponseFuncs::ChannelClose(id),
+            crate::ResponseFuncs::ChannelForceClose(id, txid) => ResponseFuncs::ChannelForceClose(id, txid),
+            crate::ResponseFuncs::ChannelCloseAll => ResponseFuncs::ChannelCloseAll,
+            crate::ResponseFuncs::ChannelList(channels) => ResponseFuncs::ChannelList(channels),
+            crate::ResponseFuncs::ChannelListClosed(channels) => ResponseFuncs::ChannelListClosed(channels),
+            crate::ResponseFuncs::ChannelLabel(id, label) => ResponseFuncs::ChannelLabel(id, label),
+            crate::ResponseFuncs::PeerList(peers) => ResponseFuncs::PeerList(peers),
+            crate::ResponseFuncs::PeerDisconnect(node_id) => ResponseFuncs::PeerDisconnect(node_id),
+            crate::ResponseFuncs::PeerBan(target) => ResponseFuncs::PeerBan(target),
+            crate::ResponseFuncs::PeerUnban(target) => ResponseFuncs::PeerUnban(target),
+            crate::ResponseFuncs::PeerBanList(banned) => ResponseFuncs::PeerBanList(banned),
+            crate::ResponseFuncs::InvoiceCreate(invoice) => ResponseFuncs::InvoiceCreate(invoice),
+            crate::ResponseFuncs::InvoicePay(payment) => ResponseFuncs::InvoicePay(payment),
+            crate::ResponseFuncs::QueryRoute(route) => ResponseFuncs::QueryRoute(route),
+            crate::ResponseFuncs::GraphNodes(nodes) => ResponseFuncs::GraphNodes(nodes),
+            crate::ResponseFuncs::GraphChannels(channels) => ResponseFuncs::GraphChannels(channels),
+            crate::ResponseFuncs::GraphNode(node) => ResponseFuncs::GraphNode(node),
+            crate::ResponseFuncs::GraphChannel(channel) => ResponseFuncs::GraphChannel(channel),
+            crate::ResponseFuncs::Event(event) => ResponseFuncs::Event(event),
+            crate::ResponseFuncs::Error(e) => ResponseFuncs::Error(e),
+        }
+    }
+}
+
+impl TryFrom<Message> for crate::Message {
+    type Error = String;
+
+    fn try_from(message: Message) -> Result<Self, String> {
+        Ok(match message {
+            Message::Request(token, req) => crate::Message::Request(token, req),
+            Message::Response(resp) => crate::Message::Response(crate::ResponseFuncs::try_from(resp)?),
+            Message::Error(code, e) => crate::Message::Error(code, e),
+        })
+    }
+}
+
+impl From<crate::Message> for Message {
+    fn from(message: crate::Message) -> Self {
+        match message {
+            crate::Message::Request(token, req) => Message::Request(token, req),
+            crate::Message::Response(resp) => Message::Response(resp.into()),
+            crate::Message::Error(code, e) => Message::Error(code, e),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use ln_manager::ln_bridge::utils::hex_str;
+use protocol::{Permission, RequestFuncs};
+use rand::{thread_rng, Rng};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+// Roles a token can be issued for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    ReadOnly,
+    Invoice,
+    Admin,
+}
+
+impl Role {
+    fn name(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "readonly",
+            Role::Invoice => "invoice",
+            Role::Admin => "admin",
+        }
+    }
+
+    // invoice clients also need node info and the event stream to see payments arrive
+    fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::ReadOnly => permission == Permission::Read,
+            Role::Invoice => permission == Permission::Read || permission == Permission::Invoice,
+            Role::Admin => true,
+        }
+    }
+}
+
+const ROLES: [Role; 3] = [Role::ReadOnly, Role::Invoice, Role::Admin];
+
+// same time whatever the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Access tokens for the control interface, one per role in {lndata}/tokens/<role>.token.
+/// Missing tokens are generated on startup, hand out the file of the role a client needs.
+pub struct Tokens {
+    tokens: Vec<(Role, String)>,
+}
+
+impl Tokens {
+    pub fn load_or_create(data_path: &str) -> io::Result<Self> {
+        let dir = format!("{}/tokens", data_path);
+        fs::create_dir_all(&dir)?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        let mut tokens = Vec::with_capacity(ROLES.len());
+        for role in ROLES.iter() {
+            let filename = format!("{}/{}.token", dir, role.name());
+            let token = match fs::read_to_string(&filename) {
+                // an empty token would match an empty or missing header
+                Ok(ref token) if token.trim().is_empty() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is empty, remove it to generate a new token", &filename),
+                    ));
+                }
+                Ok(token) => token.trim().to_string(),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    let token = hex_str(&thread_rng().gen::<[u8; 32]>());
+                    let mut f = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&filename)?;
+                    f.write_all(token.as_bytes())?;
+                    info!("Generated {} token in {}", role.name(), &filename);
+                    token
+                }
+                Err(e) => return Err(e),
+            };
+            tokens.push((*role, token));
+        }
+        Ok(Self { tokens })
+    }
+
+    fn role(&self, token: &str) -> Option<Role> {
+        self.tokens.iter()
+            .find(|(_, known)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+            .map(|(role, _)| *role)
+    }
+
+    /// Checked before a request is dispatched
+    pub fn authorize(&self, token: &str, req: &RequestFuncs) -> Result<(), String> {
+        if token.is_empty() {
+            return Err("Missing token".to_string());
+        }
+        let role = self.role(token).ok_or("Invalid token".to_string())?;
+        if role.allows(req.permission()) {
+            Ok(())
+        } else {
+            Err(format!("The {} token doesn't allow {:?} requests", role.name(), req.permission()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> Tokens {
+        Tokens {
+            tokens: vec![
+                (Role::ReadOnly, "read".to_string()),
+                (Role::Invoice, "invoice".to_string()),
+                (Role::Admin, "admin".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn role_permissions() {
+        assert!(Role::ReadOnly.allows(Permission::Read));
+        assert!(!Role::ReadOnly.allows(Permission::Invoice));
+        assert!(!Role::ReadOnly.allows(Permission::Admin));
+        assert!(Role::Invoice.allows(Permission::Read));
+        assert!(Role::Invoice.allows(Permission::Invoice));
+        assert!(!Role::Invoice.allows(Permission::Admin));
+        assert!(Role::Admin.allows(Permission::Read));
+        assert!(Role::Admin.allows(Permission::Invoice));
+        assert!(Role::Admin.allows(Permission::Admin));
+    }
+
+    #[test]
+    fn authorize_by_role() {
+        let tokens = tokens();
+        let invoice = RequestFuncs::InvoiceCreate("1000".to_string());
+        assert!(tokens.authorize("read", &RequestFuncs::GetNodeInfo).is_ok());
+        assert!(tokens.authorize("read", &invoice).is_err());
+        assert!(tokens.authorize("invoice", &RequestFuncs::GetNodeInfo).is_ok());
+        assert!(tokens.authorize("invoice", &invoice).is_ok());
+        assert!(tokens.authorize("invoice", &RequestFuncs::ChannelCloseAll).is_err());
+        assert!(tokens.authorize("admin", &RequestFuncs::ChannelCloseAll).is_ok());
+        assert!(tokens.authorize("other", &RequestFuncs::GetNodeInfo).is_err());
+    }
+
+    #[test]
+    fn empty_token_rejected() {
+        let tokens = Tokens { tokens: vec![(Role::Admin, String::new())] };
+        assert!(tokens.authorize("", &RequestFuncs::GetNodeInfo).is_err());
+
+        let data_path = std::env::temp_dir().join(format!("rustbolt-auth-{}", std::process::id()));
+        let dir = data_path.join("tokens");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("readonly.token"), "\n").unwrap();
+        assert!(Tokens::load_or_create(data_path.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&data_path).unwrap();
+    }
+}
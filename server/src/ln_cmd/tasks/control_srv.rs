@@ -0,0 +1,141 @@
+use crate::ln_cmd::auth::Tokens;
+use crate::ln_cmd::tasks::handler::handle_request;
+use crate::ln_cmd::tasks::{Arg, Probe};
+use crate::ln_manager::executor::Larva;
+use crate::ln_node::settings::Settings as NodeSettings;
+use futures::StreamExt;
+use ln_manager::LnManager;
+use protocol;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::TcpListener;
+use tokio::net::unix::UnixListener;
+
+// Serves control requests over TCP on the configured address and over a unix socket, both
+// carry length prefixed protocol::Message frames
+pub async fn gen(arg: Vec<Arg>, exec: Probe, ln_mgr: Arc<LnManager<Probe>>, tokens: Arc<Tokens>) -> Result<(), String> {
+    let node_conf: Option<&NodeSettings> = match &arg[0] {
+        Arg::NodeConf(conf) => Some(conf),
+        _ => None,
+    };
+    let server = &node_conf.unwrap().server;
+    let socket_path = server.unix_socket.clone()
+        .unwrap_or_else(|| format!("{}/rustbolt.sock", ln_mgr.settings.lightning.lndata));
+
+    let _ = exec.spawn_task(listen_unix(socket_path, exec.clone(), ln_mgr.clone(), tokens.clone()));
+    listen_tcp(server.address.clone(), exec, ln_mgr, tokens).await
+}
+
+async fn listen_tcp(address: String, exec: Probe, ln_mgr: Arc<LnManager<Probe>>, tokens: Arc<Tokens>) -> Result<(), String> {
+    let listener = TcpListener::bind(&address).await
+        .map_err(|e| format!("Could not bind {}: {}", &address, e))?;
+    info!("Lightning Server Running on: {}", &address);
+    listener
+        .incoming()
+        .for_each(|stream| {
+            match stream {
+                Ok(stream) => {
+                    let _ = exec.spawn_task(serve(stream, ln_mgr.clone(), tokens.clone()));
+                }
+                Err(e) => error!("Couldn't accept a control connection: {}", e),
+            }
+            futures::future::ready(())
+        })
+        .await;
+    Ok(())
+}
+
+async fn listen_unix(path: String, exec: Probe, ln_mgr: Arc<LnManager<Probe>>, tokens: Arc<Tokens>) -> Result<(), ()> {
+    // left over from an earlier run
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).map_err(|e| {
+        error!("Could not bind unix socket {}: {}", &path, e);
+    })?;
+    // the socket gives full control over the node's funds
+    if let Err(e) = fs::set_permissions(&path, fs::Permissions::from_mode(0o600)) {
+        error!("Could not restrict permissions of {}: {}", &path, e);
+    }
+    info!("Lightning Server Running on: {}", &path);
+    listener
+        .incoming()
+        .for_each(|stream| {
+            match stream {
+                Ok(stream) => {
+                    let _ = exec.spawn_task(serve(stream, ln_mgr.clone(), tokens.clone()));
+                }
+                Err(e) => error!("Couldn't accept a control connection: {}", e),
+            }
+            futures::future::ready(())
+        })
+        .await;
+    Ok(())
+}
+
+async fn send<S: AsyncWrite + Unpin>(stream: &mut S, envelope: protocol::Envelope) -> Result<(), ()> {
+    stream.write_all(&protocol::encode_frame(&envelope)).await.map_err(|e| {
+        debug!("Failed to send a response: {}", e);
+    })
+}
+
+// Answers requests on one connection until the client hangs up. Replies use the version of
+// the request, requests in a version we don't speak get our supported range back.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, ln_mgr: Arc<LnManager<Probe>>, tokens: Arc<Tokens>) -> Result<(), ()> {
+    use protocol::{DecodeError, Envelope, ErrorCode, Message};
+    loop {
+        let mut header = [0u8; protocol::FRAME_HEADER_SIZE];
+        if stream.read_exact(&mut header).await.is_err() {
+            // closed by the client
+            return Ok(());
+        }
+        let len = protocol::frame_len(header).map_err(|e| {
+            warn!("Dropping control connection: {}", e);
+        })?;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.map_err(|e| {
+            debug!("Control connection closed mid frame: {}", e);
+        })?;
+
+        let (version, request_id, message) = match protocol::decode_envelope(&body) {
+            Ok(Envelope::Message { version, request_id, message: Message::Request(token, req) }) => {
+                match tokens.authorize(&token, &req) {
+                    Ok(()) if req == protocol::RequestFuncs::SubscribeEvents => {
+                        return stream_events(stream, version, request_id, &ln_mgr).await;
+                    }
+                    Ok(()) => (version, request_id, Message::Response(handle_request(req, &ln_mgr).await)),
+                    Err(e) => (version, request_id, Message::Error(ErrorCode::Unauthorized, e)),
+                }
+            }
+            Ok(Envelope::Message { version, request_id, .. }) => {
+                (version, request_id, Message::Error(ErrorCode::InvalidRequest, "Expected a request".to_string()))
+            }
+            Ok(Envelope::Unsupported { request_id, .. }) | Err(DecodeError::UnsupportedVersion { request_id, .. }) => {
+                send(&mut stream, Envelope::Unsupported {
+                    request_id,
+                    min_version: protocol::MIN_PROTOCOL_VERSION,
+                    max_version: protocol::PROTOCOL_VERSION,
+                }).await?;
+                continue;
+            }
+            Err(DecodeError::Malformed(e)) => {
+                debug!("Malformed control request: {}", e);
+                (protocol::PROTOCOL_VERSION, 0, Message::Error(ErrorCode::Malformed, e))
+            }
+        };
+        send(&mut stream, Envelope::Message { version, request_id, message }).await?;
+    }
+}
+
+// The connection only carries events from here on, until either side goes away. Every event
+// is sent under the request id of the subscription.
+async fn stream_events<S: AsyncWrite + Unpin>(mut stream: S, version: u16, request_id: u64, ln_mgr: &LnManager<Probe>) -> Result<(), ()> {
+    let mut events = ln_mgr.events.subscribe();
+    while let Some(event) = events.next().await {
+        let message = protocol::Message::Response(protocol::ResponseFuncs::Event(event));
+        send(&mut stream, protocol::Envelope::Message { version, request_id, message }).await.map_err(|_| {
+            debug!("Event subscriber went away");
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::ln_cmd::tasks::Probe;
+use crate::ln_cmd::utils;
+use crate::ln_manager::ln_cmd::channel::ChannelC;
+use crate::ln_manager::ln_cmd::graph::GraphC;
+use crate::ln_manager::ln_cmd::invoice::InvoiceC;
+use crate::ln_manager::ln_cmd::peer::PeerC;
+use ln_manager::LnManager;
+use protocol;
+
+// Answers a control request, shared by every transport
+pub async fn handle_request(
+    req: protocol::RequestFuncs,
+    ln_mgr: &LnManager<Probe>,
+) -> protocol::ResponseFuncs {
+    match req {
+        protocol::RequestFuncs::GetAddresses => {
+            let addresses = utils::imported_addresses::get(
+                ln_mgr.settings.lightning.lndata.clone(),
+                ln_mgr.network.clone(),
+            );
+            protocol::ResponseFuncs::GetAddresses(addresses)
+        }
+        protocol::RequestFuncs::GetNodeInfo => {
+            let node_info = utils::node_info::get(ln_mgr);
+            protocol::ResponseFuncs::GetNodeInfo(Box::new(node_info))
+        }
+        protocol::RequestFuncs::PeerConnect { node } => match ln_mgr.connect(node).await {
+            Ok(node_id) => protocol::ResponseFuncs::PeerConnect(node_id),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::PeerList => {
+            let nodes = ln_mgr.list();
+            protocol::ResponseFuncs::PeerList(nodes)
+        }
+        protocol::RequestFuncs::PeerDisconnect { node_id } => match ln_mgr.disconnect(node_id) {
+            Ok(node_id) => protocol::ResponseFuncs::PeerDisconnect(node_id),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::PeerBan { target } => match ln_mgr.ban(target) {
+            Ok(target) => protocol::ResponseFuncs::PeerBan(target),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::PeerUnban { target } => match ln_mgr.unban(target) {
+            Ok(target) => protocol::ResponseFuncs::PeerUnban(target),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::PeerBanList => {
+            protocol::ResponseFuncs::PeerBanList(ln_mgr.ban_list())
+        }
+        protocol::RequestFuncs::ChannelCreate { node_id, value_sat, push_msat, private, close_to } => match ln_mgr.fund_channel(node_id, value_sat, push_msat, private, close_to) {
+            Ok(channel) => protocol::ResponseFuncs::ChannelCreate(channel),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::ChannelClose { channel, close_to } => match ln_mgr.close(channel, close_to) {
+            Ok(channel) => protocol::ResponseFuncs::ChannelClose(channel),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::ChannelForceClose { channel } => match ln_mgr.force_close(channel) {
+            Ok((channel, txid)) => protocol::ResponseFuncs::ChannelForceClose(channel, txid),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::ChannelCloseAll => {
+            ln_mgr.force_close_all();
+            protocol::ResponseFuncs::ChannelCloseAll
+        }
+        protocol::RequestFuncs::ChannelList { state, peer } => match ln_mgr.channel_list(state, peer) {
+            Ok(channels) => protocol::ResponseFuncs::ChannelList(channels),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::ChannelListClosed => {
+            protocol::ResponseFuncs::ChannelListClosed(ln_mgr.closed_channel_list())
+        }
+        protocol::RequestFuncs::ChannelLabel { channel, label } => match ln_mgr.label(channel, label) {
+            Ok((channel, label)) => protocol::ResponseFuncs::ChannelLabel(channel, label),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::InvoiceCreate { amount_pico_btc, description } => match ln_mgr.create_invoice(amount_pico_btc, description) {
+            Ok(invoice) => protocol::ResponseFuncs::InvoiceCreate(invoice),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::InvoicePay { invoice, amount_msat, limits } => match ln_mgr.pay(invoice, amount_msat, limits) {
+            Ok(payment) => protocol::ResponseFuncs::InvoicePay(payment),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::QueryRoute { target, amount_msat, final_cltv } => match ln_mgr.query_route(target, amount_msat, final_cltv) {
+            Ok(route) => protocol::ResponseFuncs::QueryRoute(route),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::GraphNodes => {
+            protocol::ResponseFuncs::GraphNodes(ln_mgr.graph_nodes())
+        }
+        protocol::RequestFuncs::GraphChannels => {
+            protocol::ResponseFuncs::GraphChannels(ln_mgr.graph_channels())
+        }
+        protocol::RequestFuncs::GraphNode { node } => match ln_mgr.graph_node(node) {
+            Ok(node) => protocol::ResponseFuncs::GraphNode(node),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        protocol::RequestFuncs::GraphChannel { short_channel_id } => match ln_mgr.graph_channel(short_channel_id) {
+            Ok(channel) => protocol::ResponseFuncs::GraphChannel(channel),
+            Err(e) => protocol::ResponseFuncs::Error(e),
+        },
+        // streamed by the transports that keep a connection open, see control_srv
+        protocol::RequestFuncs::SubscribeEvents => {
+            protocol::ResponseFuncs::Error("Event subscriptions need a streaming connection".to_string())
+        }
+    }
+}
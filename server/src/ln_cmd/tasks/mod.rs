@@ -1,6 +1,8 @@
+pub mod control_srv;
+pub mod handler;
 pub mod ln_mgr;
 pub mod node;
-pub mod udp_srv;
+pub mod rpc_srv;
 use futures::future::Future;
 use futures::task::{Context, Poll};
 use futures::FutureExt;
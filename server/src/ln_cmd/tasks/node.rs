@@ -1,6 +1,9 @@
-use crate::ln_cmd::tasks::{ln_mgr, udp_srv};
+use crate::ln_cmd::auth::Tokens;
+use crate::ln_cmd::tasks::{control_srv, ln_mgr, rpc_srv};
 use crate::ln_cmd::tasks::{Arg, Probe, TaskFn};
 use crate::ln_manager::executor::Larva;
+use futures::StreamExt;
+use std::sync::Arc;
 
 fn node(mut args: Vec<Arg>, exec: Probe) -> Result<(), String> {
     let executor = exec.clone();
@@ -12,9 +15,31 @@ fn node(mut args: Vec<Arg>, exec: Probe) -> Result<(), String> {
 
     let spawn_ln_mgr = ln_mgr::gen(ln_conf, executor.clone());
     let _ = exec.spawn_task(async move {
-        let ln_mgr = spawn_ln_mgr.await?;
-        let spawn_udp_srv = udp_srv::gen(node_conf, executor.clone(), ln_mgr);
-        let _ = spawn_udp_srv.await;
+        let ln_mgr = Arc::new(spawn_ln_mgr.await?);
+        let shutdown_ln_mgr = ln_mgr.clone();
+        let _ = executor.spawn_task(async move {
+            let mut ctrl_c = tokio::net::signal::ctrl_c().map_err(|e| {
+                error!("Failed to listen for ctrl-c: {}", e);
+            })?;
+            ctrl_c.next().await;
+            info!("Shutting down ...");
+            shutdown_ln_mgr.shutdown();
+            std::process::exit(0);
+        });
+        let tokens = match Tokens::load_or_create(&ln_mgr.settings.lightning.lndata) {
+            Ok(tokens) => Arc::new(tokens),
+            Err(e) => {
+                error!("Could not load access tokens: {}", e);
+                return Err(());
+            }
+        };
+        if let Err(e) = rpc_srv::gen(node_conf.clone(), executor.clone(), ln_mgr.clone(), tokens.clone()) {
+            error!("{}", e);
+        }
+        let spawn_control_srv = control_srv::gen(node_conf, executor.clone(), ln_mgr, tokens);
+        if let Err(e) = spawn_control_srv.await {
+            error!("{}", e);
+        }
         Ok(())
     });
     Ok(())
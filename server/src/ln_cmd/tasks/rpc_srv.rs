@@ -0,0 +1,157 @@
+use crate::ln_cmd::auth::Tokens;
+use crate::ln_cmd::tasks::handler::handle_request;
+use crate::ln_cmd::tasks::{Arg, Probe};
+use crate::ln_manager::executor::Larva;
+use crate::ln_node::settings::Settings as NodeSettings;
+use futures::channel::oneshot;
+use futures::TryFutureExt;
+use hyper::rt::{self, Future as Future01, Stream as Stream01};
+use hyper::service::service_fn;
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use ln_manager::LnManager;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+// the request reached the node and failed there
+const NODE_ERROR: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+
+type ResponseFuture = Box<dyn Future01<Item = Response<Body>, Error = hyper::Error> + Send>;
+
+struct Context {
+    exec: Probe,
+    ln_mgr: Arc<LnManager<Probe>>,
+    tokens: Arc<Tokens>,
+}
+
+// JSON-RPC 2.0 over HTTP POST, methods are the RequestFuncs variant names and params their
+// fields, e.g. {"jsonrpc":"2.0","id":1,"method":"ChannelList","params":{"state":"Pending"}}.
+// Clients authenticate with "Authorization: Bearer <token>".
+pub fn gen(arg: Vec<Arg>, exec: Probe, ln_mgr: Arc<LnManager<Probe>>, tokens: Arc<Tokens>) -> Result<(), String> {
+    let node_conf: Option<&NodeSettings> = match &arg[0] {
+        Arg::NodeConf(conf) => Some(conf),
+        _ => None,
+    };
+    let address = match &node_conf.unwrap().server.rpc_address {
+        Some(address) => address.clone(),
+        None => return Ok(()),
+    };
+    let addr: SocketAddr = address.parse()
+        .map_err(|_| format!("Invalid rpc_address: {}", &address))?;
+
+    let ctx = Arc::new(Context { exec, ln_mgr, tokens });
+    let server = Server::try_bind(&addr)
+        .map_err(|e| format!("Could not bind {}: {}", &address, e))?
+        .serve(move || {
+            let ctx = ctx.clone();
+            service_fn(move |req| handle(req, ctx.clone()))
+        })
+        .map_err(|e| error!("JSON-RPC server failed: {}", e));
+    info!("JSON-RPC Server Running on: {}", &address);
+    // hyper brings its own runtime, requests are handed to the node's executor
+    thread::spawn(move || rt::run(server));
+    Ok(())
+}
+
+fn reply(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn rpc_error(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn respond(status: StatusCode, body: Value) -> ResponseFuture {
+    Box::new(rt::lazy(move || Ok::<_, hyper::Error>(reply(status, body))))
+}
+
+fn bearer_token(req: &Request<Body>) -> String {
+    let value = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .trim();
+    if value.len() > 7 && value[..7].eq_ignore_ascii_case("bearer ") {
+        value[7..].trim().to_string()
+    } else {
+        String::new()
+    }
+}
+
+// RequestFuncs is externally tagged: unit variants are plain strings, struct variants take their
+// fields by name or, as a params array, in declaration order
+fn parse_request(method: &str, params: Option<Value>) -> Option<protocol::RequestFuncs> {
+    match params {
+        None | Some(Value::Null) => serde_json::from_value(Value::String(method.to_string())).ok(),
+        Some(params) => serde_json::from_value(json!({ method: params })).ok(),
+    }
+}
+
+// the variant's fields, without the tag
+fn result_value(resp: protocol::ResponseFuncs) -> Value {
+    match serde_json::to_value(resp) {
+        Ok(Value::Object(tagged)) if tagged.len() == 1 => {
+            tagged.into_iter().next().map_or(Value::Null, |(_, fields)| fields)
+        }
+        _ => Value::Null,
+    }
+}
+
+fn handle(req: Request<Body>, ctx: Arc<Context>) -> ResponseFuture {
+    if req.method() != Method::POST {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, rpc_error(Value::Null, INVALID_REQUEST, "Use POST".to_string()));
+    }
+    let token = bearer_token(&req);
+    Box::new(req.into_body().concat2().and_then(move |body| -> ResponseFuture {
+        let call: Value = match serde_json::from_slice(&body) {
+            Ok(call) => call,
+            Err(e) => return respond(StatusCode::OK, rpc_error(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let method = match (call.get("jsonrpc").and_then(Value::as_str), call.get("method").and_then(Value::as_str)) {
+            (Some("2.0"), Some(method)) => method.to_string(),
+            _ => return respond(StatusCode::OK, rpc_error(id, INVALID_REQUEST, "Not a JSON-RPC 2.0 request".to_string())),
+        };
+        let request = match parse_request(&method, call.get("params").cloned()) {
+            Some(request) => request,
+            None => return respond(StatusCode::OK, rpc_error(id, INVALID_PARAMS, format!("Unknown method or invalid params for {}", method))),
+        };
+        if let Err(e) = ctx.tokens.authorize(&token, &request) {
+            return respond(StatusCode::UNAUTHORIZED, rpc_error(id, UNAUTHORIZED, e));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let ln_mgr = ctx.ln_mgr.clone();
+        let _ = ctx.exec.spawn_task(async move {
+            let _ = tx.send(handle_request(request, &ln_mgr).await);
+            Ok(())
+        });
+        Box::new(rx.compat().then(move |resp| {
+            let body = match resp {
+                Ok(protocol::ResponseFuncs::Error(e)) => rpc_error(id, NODE_ERROR, e),
+                Ok(resp) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": result_value(resp),
+                }),
+                Err(_) => rpc_error(id, INTERNAL_ERROR, "Request was dropped".to_string()),
+            };
+            Ok::<_, hyper::Error>(reply(StatusCode::OK, body))
+        }))
+    }))
+}
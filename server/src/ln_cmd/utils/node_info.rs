@@ -1,10 +1,39 @@
+use crate::lightning::chain::keysinterface::KeysInterface;
+use crate::ln_cmd::tasks::Probe;
+use crate::ln_manager::ln_cmd::channel::ChannelC;
 use ln_manager::ln_bridge::utils::hex_str;
+use ln_manager::utils::network_name;
+use ln_manager::LnManager;
+use protocol::{ChannelState, NodeInfo};
 use secp256k1::key::PublicKey;
-use secp256k1::{self, Secp256k1};
+use secp256k1::Secp256k1;
 
-pub fn get(node_secret: &secp256k1::key::SecretKey) -> String {
-    String::from(format!(
-        "{}",
-        hex_str(&PublicKey::from_secret_key(&Secp256k1::new(), node_secret).serialize())
-    ))
+// getinfo: who we are, how far along the chain we are and a summary of channels and peers
+pub fn get(ln_mgr: &LnManager<Probe>) -> NodeInfo {
+    let node_id = PublicKey::from_secret_key(&Secp256k1::new(), &ln_mgr.keys.get_node_secret());
+    let tip = ln_mgr.chain_tip.get();
+    let channels = ln_mgr.channel_list(None, None).unwrap_or_default();
+    let count = |state: ChannelState| channels.iter().filter(|c| c.state == state).count() as u32;
+
+    let mut addresses = ln_mgr.settings.node.addresses.clone();
+    addresses.extend(ln_mgr.settings.lightning.onion_address.clone());
+
+    NodeInfo {
+        node_id: hex_str(&node_id.serialize()),
+        alias: ln_mgr.settings.node.alias.clone(),
+        network: network_name(ln_mgr.network).to_string(),
+        port: ln_mgr.settings.lightning.port,
+        listen_addresses: ln_mgr.settings.lightning.listen_addresses(),
+        addresses,
+        block_height: tip.height,
+        synced_to_chain: tip.is_synced(),
+        block_hash: tip.hash,
+        num_active_channels: count(ChannelState::Active),
+        num_pending_channels: count(ChannelState::Pending),
+        num_inactive_channels: count(ChannelState::Inactive),
+        num_peers: ln_mgr.peer_manager.get_peer_node_ids().len() as u32,
+        spendable_msat: channels.iter().filter(|c| c.is_usable).map(|c| c.outbound_capacity_msat).sum(),
+        receivable_msat: channels.iter().filter(|c| c.is_usable).map(|c| c.inbound_capacity_msat).sum(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
 }
@@ -2,7 +2,12 @@ use config::{Config, ConfigError, File}; // clap-rs
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Server {
+    // TCP control address
     pub address: String,
+    // defaults to rustbolt.sock in lndata
+    pub unix_socket: Option<String>,
+    // JSON-RPC over HTTP, disabled when unset
+    pub rpc_address: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
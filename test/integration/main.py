@@ -114,7 +114,10 @@ def run_server(server_id, build_dir, version, env):
 def run_cli(build_dir, env, cmd):
     print_exec("rbcli {}".format(" ".join(cmd)))
     cli_bin =  build_dir + env["cli"]["bin"]
-    return json.loads(subprocess.check_output([cli_bin, "-j"] + cmd).decode('ascii'))
+    # node #2 listens on 8124, node #1 on the default 8123
+    server_id = 2 if any(c.endswith(":8124") for c in cmd) else 1
+    token = env["server"]["test"] + "ln/data_{}/tokens/admin.token".format(server_id)
+    return json.loads(subprocess.check_output([cli_bin, "-j", "-t", token] + cmd).decode('ascii'))
 
 def fund(addr, amount, cli):
     res = cli.req("sendtoaddress", [addr, amount])